@@ -3,7 +3,7 @@
 //! This module contains the raw deserialized representation of an
 //! account in the accounts section of the user configuration file.
 
-use std::path::PathBuf;
+use std::{collections::HashMap, ops::Deref, path::PathBuf};
 
 use crossterm::style::Color;
 #[cfg(feature = "pgp")]
@@ -19,10 +19,16 @@ use email::sendmail::config::SendmailConfig;
 #[cfg(feature = "smtp")]
 use email::smtp::config::SmtpConfig;
 use email::{account::config::AccountConfig, template::config::TemplateConfig};
-use pimalaya_tui::config::toml::himalaya::config::{
-    BackendKind, EnvelopeConfig, FolderConfig, MessageConfig,
+use pimalaya_tui::config::toml::himalaya::config::{EnvelopeConfig, FolderConfig, MessageConfig};
+use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use toml::{Table, Value};
+
+#[cfg(feature = "memory")]
+use crate::memory::config::MemoryConfig;
+use crate::{
+    backend::{BackendKind, BackendRoutes},
+    envelope::{Column, ColumnConfig, DateTimeMode},
 };
-use serde::{Deserialize, Serialize};
 
 /// Represents all existing kind of account config.
 #[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
@@ -35,13 +41,15 @@ pub struct TomlAccountConfig {
     pub signature_delim: Option<String>,
     pub downloads_dir: Option<PathBuf>,
     pub backend: Option<BackendKind>,
+    pub backends: Option<BackendRoutes>,
+    pub identities: Option<HashMap<String, TomlIdentityConfig>>,
 
     #[cfg(feature = "pgp")]
     pub pgp: Option<PgpConfig>,
 
     pub folder: Option<FolderConfig>,
-    pub envelope: Option<EnvelopeConfig>,
-    pub message: Option<MessageConfig>,
+    pub envelope: Option<TomlEnvelopeConfig>,
+    pub message: Option<TomlMessageConfig>,
     pub template: Option<TemplateConfig>,
 
     #[cfg(feature = "imap")]
@@ -54,6 +62,196 @@ pub struct TomlAccountConfig {
     pub smtp: Option<SmtpConfig>,
     #[cfg(feature = "sendmail")]
     pub sendmail: Option<SendmailConfig>,
+    #[cfg(feature = "memory")]
+    pub memory: Option<MemoryConfig>,
+}
+
+/// Represents an alternative sender identity of an account.
+///
+/// Options left empty are inherited from the account.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TomlIdentityConfig {
+    pub email: String,
+    pub display_name: Option<String>,
+    pub signature: Option<String>,
+    pub signature_delim: Option<String>,
+}
+
+/// Represents the envelope config of an account.
+///
+/// Extends the shared envelope config with options specific to the
+/// REPL, which are extracted before deserializing the shared ones.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TomlEnvelopeConfig {
+    pub base: EnvelopeConfig,
+    pub list_datetime_mode: Option<DateTimeMode>,
+    pub list_table_columns: Option<Vec<ColumnConfig>>,
+}
+
+impl Deref for TomlEnvelopeConfig {
+    type Target = EnvelopeConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl From<EnvelopeConfig> for TomlEnvelopeConfig {
+    fn from(base: EnvelopeConfig) -> Self {
+        Self {
+            base,
+            list_datetime_mode: None,
+            list_table_columns: None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TomlEnvelopeConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut table = Table::deserialize(deserializer)?;
+
+        let list_datetime_mode = take_value(&mut table, &["list"], "datetime-mode")
+            .map(Value::try_into)
+            .transpose()
+            .map_err(D::Error::custom)?;
+
+        let list_table_columns = take_value(&mut table, &["list", "table"], "columns")
+            .map(Value::try_into)
+            .transpose()
+            .map_err(D::Error::custom)?;
+
+        let base = Value::Table(table).try_into().map_err(D::Error::custom)?;
+
+        Ok(Self {
+            base,
+            list_datetime_mode,
+            list_table_columns,
+        })
+    }
+}
+
+impl Serialize for TomlEnvelopeConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut table = Table::try_from(&self.base).map_err(S::Error::custom)?;
+
+        if let Some(mode) = &self.list_datetime_mode {
+            let mode = Value::try_from(mode).map_err(S::Error::custom)?;
+            insert_value(&mut table, &["list"], "datetime-mode", mode)
+                .ok_or_else(|| S::Error::custom("envelope.list should be a table"))?;
+        }
+
+        if let Some(columns) = &self.list_table_columns {
+            let columns = Value::try_from(columns).map_err(S::Error::custom)?;
+            insert_value(&mut table, &["list", "table"], "columns", columns)
+                .ok_or_else(|| S::Error::custom("envelope.list.table should be a table"))?;
+        }
+
+        table.serialize(serializer)
+    }
+}
+
+/// Represents the message config of an account.
+///
+/// Extends the shared message config with the styles of the read
+/// view, which are extracted before deserializing the shared ones.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TomlMessageConfig {
+    pub base: MessageConfig,
+    pub read_styles: ReadStylesConfig,
+}
+
+/// Represents the styles of the read view. Unset ones are taken from
+/// the theme.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReadStylesConfig {
+    pub header_name_color: Option<Color>,
+    pub header_value_color: Option<Color>,
+    pub quote_colors: Option<Vec<Color>>,
+    pub url_color: Option<Color>,
+    pub signature_color: Option<Color>,
+}
+
+impl ReadStylesConfig {
+    const KEYS: [&'static str; 5] = [
+        "header-name-color",
+        "header-value-color",
+        "quote-colors",
+        "url-color",
+        "signature-color",
+    ];
+}
+
+impl Deref for TomlMessageConfig {
+    type Target = MessageConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl From<MessageConfig> for TomlMessageConfig {
+    fn from(base: MessageConfig) -> Self {
+        Self {
+            base,
+            read_styles: Default::default(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TomlMessageConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut table = Table::deserialize(deserializer)?;
+
+        let read_styles: Table = ReadStylesConfig::KEYS
+            .into_iter()
+            .filter_map(|key| Some((key.to_owned(), take_value(&mut table, &["read"], key)?)))
+            .collect();
+
+        let read_styles = Value::Table(read_styles)
+            .try_into()
+            .map_err(D::Error::custom)?;
+
+        let base = Value::Table(table).try_into().map_err(D::Error::custom)?;
+
+        Ok(Self { base, read_styles })
+    }
+}
+
+impl Serialize for TomlMessageConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut table = Table::try_from(&self.base).map_err(S::Error::custom)?;
+        let read_styles = Table::try_from(&self.read_styles).map_err(S::Error::custom)?;
+
+        for (key, value) in read_styles {
+            insert_value(&mut table, &["read"], &key, value)
+                .ok_or_else(|| S::Error::custom("message.read should be a table"))?;
+        }
+
+        table.serialize(serializer)
+    }
+}
+
+/// Removes the value at the given path of the given table.
+fn take_value(table: &mut Table, path: &[&str], key: &str) -> Option<Value> {
+    path.iter()
+        .try_fold(table, |table, key| table.get_mut(*key)?.as_table_mut())?
+        .remove(key)
+}
+
+/// Inserts the given value at the given path of the given table,
+/// creating intermediate tables.
+fn insert_value(table: &mut Table, path: &[&str], key: &str, value: Value) -> Option<()> {
+    let table = path.iter().try_fold(table, |table, key| {
+        table
+            .entry(*key)
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+    })?;
+
+    table.insert(key.to_owned(), value);
+    Some(())
 }
 
 impl From<TomlAccountConfig> for AccountConfig {
@@ -70,15 +268,19 @@ impl From<TomlAccountConfig> for AccountConfig {
             pgp: config.pgp,
 
             folder: config.folder.map(Into::into),
-            envelope: config.envelope.map(Into::into),
+            envelope: config.envelope.map(|c| c.base.into()),
             flag: None,
-            message: config.message.map(Into::into),
+            message: config.message.map(|c| c.base.into()),
             template: config.template,
         }
     }
 }
 
 impl TomlAccountConfig {
+    pub fn envelope_list_datetime_mode(&self) -> Option<DateTimeMode> {
+        self.envelope.as_ref().and_then(|c| c.list_datetime_mode)
+    }
+
     pub fn envelope_list_table_preset(&self) -> Option<String> {
         self.envelope.as_ref().and_then(|c| c.list_table_preset())
     }
@@ -134,4 +336,18 @@ impl TomlAccountConfig {
             .as_ref()
             .and_then(|c| c.list_table_date_color())
     }
+
+    pub fn envelope_list_table_columns(&self) -> Option<Vec<Column>> {
+        self.envelope
+            .as_ref()
+            .and_then(|c| c.list_table_columns.clone())
+            .map(|columns| columns.into_iter().map(Into::into).collect())
+    }
+
+    pub fn message_read_styles(&self) -> ReadStylesConfig {
+        self.message
+            .as_ref()
+            .map(|c| c.read_styles.clone())
+            .unwrap_or_default()
+    }
 }
@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use color_eyre::{eyre::eyre, Result};
 #[cfg(feature = "imap")]
 use email::imap::{config::ImapConfig, ImapContext, ImapContextBuilder};
 #[cfg(feature = "maildir")]
@@ -15,14 +16,61 @@ use email::{
         mapper::SomeBackendContextBuilderMapper,
     },
     envelope::list::ListEnvelopes,
+    flag::add::AddFlags,
     folder::list::ListFolders,
     message::{
         add::AddMessage, copy::CopyMessages, delete::DeleteMessages, get::GetMessages,
-        r#move::MoveMessages, send::SendMessage,
+        peek::PeekMessages, r#move::MoveMessages, send::SendMessage,
     },
     AnyResult,
 };
-use pimalaya_tui::config::toml::himalaya::config::BackendKind;
+use pimalaya_tui::config::toml::himalaya::config::BackendKind as TomlBackendKind;
+#[cfg(feature = "sendmail")]
+use process::SingleCommand;
+use serde::{Deserialize, Serialize};
+
+use crate::account::config::TomlAccountConfig;
+#[cfg(feature = "memory")]
+use crate::memory::{config::MemoryConfig, MemoryContextBuilder, MemoryContextSync};
+
+/// The kind of backend, as found in the TOML configuration, extended
+/// with the in-memory backend.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    #[default]
+    None,
+    #[cfg(feature = "imap")]
+    Imap,
+    #[cfg(feature = "maildir")]
+    Maildir,
+    #[cfg(feature = "notmuch")]
+    Notmuch,
+    #[cfg(feature = "smtp")]
+    Smtp,
+    #[cfg(feature = "sendmail")]
+    Sendmail,
+    #[cfg(feature = "memory")]
+    Memory,
+}
+
+impl From<TomlBackendKind> for BackendKind {
+    fn from(kind: TomlBackendKind) -> Self {
+        match kind {
+            TomlBackendKind::None => Self::None,
+            #[cfg(feature = "imap")]
+            TomlBackendKind::Imap => Self::Imap,
+            #[cfg(feature = "maildir")]
+            TomlBackendKind::Maildir => Self::Maildir,
+            #[cfg(feature = "notmuch")]
+            TomlBackendKind::Notmuch => Self::Notmuch,
+            #[cfg(feature = "smtp")]
+            TomlBackendKind::Smtp => Self::Smtp,
+            #[cfg(feature = "sendmail")]
+            TomlBackendKind::Sendmail => Self::Sendmail,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum BackendConfig {
@@ -36,6 +84,8 @@ pub enum BackendConfig {
     Smtp(SmtpConfig),
     #[cfg(feature = "sendmail")]
     Sendmail(SendmailConfig),
+    #[cfg(feature = "memory")]
+    Memory(MemoryConfig),
 }
 
 #[derive(BackendContext)]
@@ -50,6 +100,8 @@ pub struct Context {
     smtp: Option<SmtpContextSync>,
     #[cfg(feature = "sendmail")]
     sendmail: Option<SendmailContextSync>,
+    #[cfg(feature = "memory")]
+    memory: Option<MemoryContextSync>,
 }
 
 #[cfg(feature = "imap")]
@@ -87,10 +139,52 @@ impl AsRef<Option<SendmailContextSync>> for Context {
     }
 }
 
+#[cfg(feature = "memory")]
+impl AsRef<Option<MemoryContextSync>> for Context {
+    fn as_ref(&self) -> &Option<MemoryContextSync> {
+        &self.memory
+    }
+}
+
+/// Backends overriding the default one for specific features, like
+/// listing envelopes from Notmuch while adding drafts to IMAP.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BackendRoutes {
+    pub list_folders: Option<BackendKind>,
+    pub list_envelopes: Option<BackendKind>,
+    pub add_flags: Option<BackendKind>,
+    pub peek_messages: Option<BackendKind>,
+    pub get_messages: Option<BackendKind>,
+    pub add_message: Option<BackendKind>,
+    pub copy_messages: Option<BackendKind>,
+    pub move_messages: Option<BackendKind>,
+    pub delete_messages: Option<BackendKind>,
+}
+
+impl BackendRoutes {
+    fn iter(&self) -> impl Iterator<Item = (&'static str, &BackendKind)> {
+        [
+            ("list-folders", &self.list_folders),
+            ("list-envelopes", &self.list_envelopes),
+            ("add-flags", &self.add_flags),
+            ("peek-messages", &self.peek_messages),
+            ("get-messages", &self.get_messages),
+            ("add-message", &self.add_message),
+            ("copy-messages", &self.copy_messages),
+            ("move-messages", &self.move_messages),
+            ("delete-messages", &self.delete_messages),
+        ]
+        .into_iter()
+        .filter_map(|(feature, kind)| Some((feature, kind.as_ref()?)))
+    }
+}
+
 #[derive(Clone)]
 pub struct ContextBuilder {
     pub backend: BackendKind,
     pub sending_backend: BackendKind,
+    pub routes: BackendRoutes,
 
     #[cfg(feature = "imap")]
     pub imap: Option<ImapContextBuilder>,
@@ -102,6 +196,77 @@ pub struct ContextBuilder {
     pub sendmail: Option<SendmailContextBuilder>,
     #[cfg(feature = "smtp")]
     pub smtp: Option<SmtpContextBuilder>,
+    #[cfg(feature = "memory")]
+    pub memory: Option<MemoryContextBuilder>,
+}
+
+impl ContextBuilder {
+    /// Returns the backend of a feature, which is the default backend
+    /// unless the feature is routed to another one.
+    fn route<'a>(&'a self, route: &'a Option<BackendKind>) -> &'a BackendKind {
+        route.as_ref().unwrap_or(&self.backend)
+    }
+
+    /// Returns the backend listing envelopes, which also defines the
+    /// identifiers of envelopes.
+    pub fn list_envelopes_backend(&self) -> &BackendKind {
+        self.route(&self.routes.list_envelopes)
+    }
+
+    /// Returns `true` if the given backend can store emails and has a
+    /// context builder.
+    fn is_storage_configured(&self, kind: &BackendKind) -> bool {
+        match kind {
+            #[cfg(feature = "imap")]
+            BackendKind::Imap => self.imap.is_some(),
+            #[cfg(feature = "maildir")]
+            BackendKind::Maildir => self.maildir.is_some(),
+            #[cfg(feature = "notmuch")]
+            BackendKind::Notmuch => self.notmuch.is_some(),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.memory.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Checks that features are routed to configured storage
+    /// backends, and that features working with envelope identifiers
+    /// share the same backend, since identifiers differ from one
+    /// backend to another.
+    pub fn validate(&self) -> Result<()> {
+        for (feature, kind) in self.routes.iter() {
+            if !self.is_storage_configured(kind) {
+                return Err(eyre!(
+                    "cannot use backend {kind:?} for feature {feature}: \
+                     backend is either not configured or cannot store emails"
+                ));
+            }
+        }
+
+        let ids_backend = self.list_envelopes_backend();
+        let id_routes = [
+            ("add-flags", &self.routes.add_flags),
+            ("peek-messages", &self.routes.peek_messages),
+            ("get-messages", &self.routes.get_messages),
+            ("copy-messages", &self.routes.copy_messages),
+            ("move-messages", &self.routes.move_messages),
+            ("delete-messages", &self.routes.delete_messages),
+        ];
+
+        for (feature, route) in id_routes {
+            let kind = self.route(route);
+
+            if kind != ids_backend {
+                return Err(eyre!(
+                    "cannot use backend {kind:?} for feature {feature}: \
+                     envelopes are listed with backend {ids_backend:?}, \
+                     whose identifiers are not understood by {kind:?}"
+                ));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -109,49 +274,85 @@ impl BackendContextBuilder for ContextBuilder {
     type Context = Context;
 
     fn list_folders(&self) -> Option<BackendFeature<Self::Context, dyn ListFolders>> {
-        match self.backend {
+        match self.route(&self.routes.list_folders) {
             #[cfg(feature = "imap")]
             BackendKind::Imap => self.list_folders_with_some(&self.imap),
             #[cfg(feature = "maildir")]
             BackendKind::Maildir => self.list_folders_with_some(&self.maildir),
             #[cfg(feature = "notmuch")]
             BackendKind::Notmuch => self.list_folders_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.list_folders_with_some(&self.memory),
             _ => None,
         }
     }
 
     fn list_envelopes(&self) -> Option<BackendFeature<Self::Context, dyn ListEnvelopes>> {
-        match self.backend {
+        match self.route(&self.routes.list_envelopes) {
             #[cfg(feature = "imap")]
             BackendKind::Imap => self.list_envelopes_with_some(&self.imap),
             #[cfg(feature = "maildir")]
             BackendKind::Maildir => self.list_envelopes_with_some(&self.maildir),
             #[cfg(feature = "notmuch")]
             BackendKind::Notmuch => self.list_envelopes_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.list_envelopes_with_some(&self.memory),
+            _ => None,
+        }
+    }
+
+    fn add_flags(&self) -> Option<BackendFeature<Self::Context, dyn AddFlags>> {
+        match self.route(&self.routes.add_flags) {
+            #[cfg(feature = "imap")]
+            BackendKind::Imap => self.add_flags_with_some(&self.imap),
+            #[cfg(feature = "maildir")]
+            BackendKind::Maildir => self.add_flags_with_some(&self.maildir),
+            #[cfg(feature = "notmuch")]
+            BackendKind::Notmuch => self.add_flags_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.add_flags_with_some(&self.memory),
+            _ => None,
+        }
+    }
+
+    fn peek_messages(&self) -> Option<BackendFeature<Self::Context, dyn PeekMessages>> {
+        match self.route(&self.routes.peek_messages) {
+            #[cfg(feature = "imap")]
+            BackendKind::Imap => self.peek_messages_with_some(&self.imap),
+            #[cfg(feature = "maildir")]
+            BackendKind::Maildir => self.peek_messages_with_some(&self.maildir),
+            #[cfg(feature = "notmuch")]
+            BackendKind::Notmuch => self.peek_messages_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.peek_messages_with_some(&self.memory),
             _ => None,
         }
     }
 
     fn get_messages(&self) -> Option<BackendFeature<Self::Context, dyn GetMessages>> {
-        match self.backend {
+        match self.route(&self.routes.get_messages) {
             #[cfg(feature = "imap")]
             BackendKind::Imap => self.get_messages_with_some(&self.imap),
             #[cfg(feature = "maildir")]
             BackendKind::Maildir => self.get_messages_with_some(&self.maildir),
             #[cfg(feature = "notmuch")]
             BackendKind::Notmuch => self.get_messages_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.get_messages_with_some(&self.memory),
             _ => None,
         }
     }
 
     fn add_message(&self) -> Option<BackendFeature<Self::Context, dyn AddMessage>> {
-        match self.backend {
+        match self.route(&self.routes.add_message) {
             #[cfg(feature = "imap")]
             BackendKind::Imap => self.add_message_with_some(&self.imap),
             #[cfg(feature = "maildir")]
             BackendKind::Maildir => self.add_message_with_some(&self.maildir),
             #[cfg(feature = "notmuch")]
             BackendKind::Notmuch => self.add_message_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.add_message_with_some(&self.memory),
             _ => None,
         }
     }
@@ -167,37 +368,43 @@ impl BackendContextBuilder for ContextBuilder {
     }
 
     fn copy_messages(&self) -> Option<BackendFeature<Self::Context, dyn CopyMessages>> {
-        match self.backend {
+        match self.route(&self.routes.copy_messages) {
             #[cfg(feature = "imap")]
             BackendKind::Imap => self.copy_messages_with_some(&self.imap),
             #[cfg(feature = "maildir")]
             BackendKind::Maildir => self.copy_messages_with_some(&self.maildir),
             #[cfg(feature = "notmuch")]
             BackendKind::Notmuch => self.copy_messages_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.copy_messages_with_some(&self.memory),
             _ => None,
         }
     }
 
     fn move_messages(&self) -> Option<BackendFeature<Self::Context, dyn MoveMessages>> {
-        match self.backend {
+        match self.route(&self.routes.move_messages) {
             #[cfg(feature = "imap")]
             BackendKind::Imap => self.move_messages_with_some(&self.imap),
             #[cfg(feature = "maildir")]
             BackendKind::Maildir => self.move_messages_with_some(&self.maildir),
             #[cfg(feature = "notmuch")]
             BackendKind::Notmuch => self.move_messages_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.move_messages_with_some(&self.memory),
             _ => None,
         }
     }
 
     fn delete_messages(&self) -> Option<BackendFeature<Self::Context, dyn DeleteMessages>> {
-        match self.backend {
+        match self.route(&self.routes.delete_messages) {
             #[cfg(feature = "imap")]
             BackendKind::Imap => self.delete_messages_with_some(&self.imap),
             #[cfg(feature = "maildir")]
             BackendKind::Maildir => self.delete_messages_with_some(&self.maildir),
             #[cfg(feature = "notmuch")]
             BackendKind::Notmuch => self.delete_messages_with_some(&self.notmuch),
+            #[cfg(feature = "memory")]
+            BackendKind::Memory => self.delete_messages_with_some(&self.memory),
             _ => None,
         }
     }
@@ -233,6 +440,12 @@ impl BackendContextBuilder for ContextBuilder {
             None => None,
         };
 
+        #[cfg(feature = "memory")]
+        let memory = match self.memory {
+            Some(memory) => Some(memory.build().await?),
+            None => None,
+        };
+
         Ok(Context {
             #[cfg(feature = "imap")]
             imap,
@@ -244,8 +457,116 @@ impl BackendContextBuilder for ContextBuilder {
             smtp,
             #[cfg(feature = "sendmail")]
             sendmail,
+            #[cfg(feature = "memory")]
+            memory,
         })
     }
 }
 
 pub type Backend = email::backend::Backend<Context>;
+
+/// Sends the given raw message from the given address to the given
+/// one, whatever the recipients found in its headers.
+///
+/// Sending backends of email-lib take envelope recipients from the
+/// message headers, which does not suit bounced messages: their
+/// headers are left untouched, so the original recipients would
+/// receive them again.
+pub async fn send_message_to(
+    config: &TomlAccountConfig,
+    backend: &Backend,
+    kind: &BackendKind,
+    from: &str,
+    to: &str,
+    msg: &[u8],
+) -> Result<()> {
+    match kind {
+        #[cfg(feature = "smtp")]
+        BackendKind::Smtp => {
+            let ctx = backend
+                .context
+                .smtp
+                .as_ref()
+                .ok_or(eyre!("cannot find SMTP context"))?;
+
+            // the SMTP context is already connected and authenticated
+            // according to the account configuration
+            let msg = mail_send::smtp::message::Message::new(from, [to], msg);
+            ctx.lock().await.send(msg).await?;
+            Ok(())
+        }
+        #[cfg(feature = "sendmail")]
+        BackendKind::Sendmail => {
+            let config = config
+                .sendmail
+                .as_ref()
+                .ok_or(eyre!("cannot find sendmail configuration"))?;
+            send_sendmail_message_to(config, to, msg).await
+        }
+        _ => Err(eyre!("cannot send message: no sending backend configured")),
+    }
+}
+
+/// Sendmail options taking a value, which may be glued to them.
+#[cfg(feature = "sendmail")]
+static SENDMAIL_VALUE_OPTIONS: [char; 14] = [
+    'B', 'C', 'F', 'L', 'N', 'R', 'V', 'X', 'f', 'h', 'o', 'p', 'q', 'r',
+];
+
+/// Checks whether the given sendmail arguments contain the `-t`
+/// option, possibly grouped with other options like in `-ti`.
+#[cfg(feature = "sendmail")]
+fn has_sendmail_t_option(args: &[String]) -> bool {
+    args.iter()
+        .skip(1)
+        .filter_map(|arg| arg.strip_prefix('-'))
+        .filter(|opts| !opts.starts_with('-'))
+        .any(|opts| {
+            opts.chars()
+                .take_while(|c| !SENDMAIL_VALUE_OPTIONS.contains(c))
+                .any(|c| c == 't')
+        })
+}
+
+/// Pipes the given message to the sendmail command, with the given
+/// recipient as argument.
+///
+/// Commands with the `-t` option are rejected, since sendmail would
+/// then also deliver the message to the recipients of its headers.
+#[cfg(feature = "sendmail")]
+async fn send_sendmail_message_to(config: &SendmailConfig, to: &str, msg: &[u8]) -> Result<()> {
+    let cmd = config.cmd.to_string();
+    let mut args = shlex::split(&cmd).ok_or(eyre!("cannot parse sendmail command {cmd}"))?;
+
+    if has_sendmail_t_option(&args) {
+        return Err(eyre!(
+            "cannot send message to {to}: sendmail command {cmd} reads recipients from headers (-t)"
+        ));
+    }
+
+    args.push(String::from("--"));
+    args.push(to.to_owned());
+
+    let cmd = shlex::try_join(args.iter().map(String::as_str))?;
+    SingleCommand::from(cmd).run_with(msg).await?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sendmail"))]
+mod tests {
+    use super::has_sendmail_t_option;
+
+    #[test]
+    fn sendmail_t_option() {
+        let args = |cmd: &str| shlex::split(cmd).unwrap();
+
+        assert!(has_sendmail_t_option(&args("sendmail -t")));
+        assert!(has_sendmail_t_option(&args("sendmail -ti")));
+        assert!(has_sendmail_t_option(&args("sendmail -oi -t")));
+        assert!(!has_sendmail_t_option(&args("sendmail -oi")));
+        assert!(!has_sendmail_t_option(&args(
+            "/opt/test/sendmail -ftest@localhost"
+        )));
+        assert!(!has_sendmail_t_option(&args("msmtp --read-envelope-from")));
+    }
+}
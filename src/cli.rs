@@ -26,6 +26,16 @@ pub struct Cli {
     #[arg(long, short, name = "account", value_name = "NAME")]
     pub account: Option<String>,
 
+    /// Start in offline mode.
+    ///
+    /// Messages are read from the local Maildir cache populated by
+    /// the `sync` command, and changes are journaled until the `online`
+    /// command replays them against IMAP. Offline mode is also enabled
+    /// when IMAP is unreachable at startup.
+    #[cfg(feature = "sync")]
+    #[arg(long)]
+    pub offline: bool,
+
     /// Enable logs with spantrace.
     ///
     /// This is the same as running the command with `RUST_LOG=debug`
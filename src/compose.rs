@@ -0,0 +1,136 @@
+//! Inline composition module.
+//!
+//! This module allows to compose short messages directly from the
+//! REPL, without spawning an external editor.
+
+use std::{io::Write, sync::Arc};
+
+use color_eyre::Result;
+use email::{account::config::AccountConfig, message::Message, template::Template};
+use reedline::{
+    ColumnarMenu, DefaultCompleter, DefaultPrompt, DefaultPromptSegment, Reedline, ReedlineMenu,
+    Signal, ValidationResult, Validator,
+};
+
+use crate::{config::KeybindsStyle, edit_mode};
+
+/// Validator keeping the body prompt open until a line containing a
+/// single dot is entered.
+struct BodyValidator;
+
+impl Validator for BodyValidator {
+    fn validate(&self, line: &str) -> ValidationResult {
+        if line.lines().last().map(str::trim) == Some(".") {
+            ValidationResult::Complete
+        } else {
+            ValidationResult::Incomplete
+        }
+    }
+}
+
+/// Prompts a single header value, with the given addresses available
+/// for completion.
+///
+/// Returns `None` if the user interrupted the composition.
+fn read_header(
+    keybinds: &KeybindsStyle,
+    name: &str,
+    addresses: &[String],
+) -> Result<Option<String>> {
+    let completer = DefaultCompleter::new_with_wordlen(addresses.to_vec(), 2)
+        .with_inclusions(&['@', '.', '-', '_', '+']);
+    let completion = Box::new(ColumnarMenu::default().with_name("completion"));
+
+    let mut reedline = Reedline::create()
+        .with_completer(Box::new(completer))
+        .with_menu(ReedlineMenu::EngineCompleter(completion))
+        .with_edit_mode(edit_mode(keybinds));
+
+    let prompt = DefaultPrompt::new(
+        DefaultPromptSegment::Basic(name.to_owned()),
+        DefaultPromptSegment::Empty,
+    );
+
+    match reedline.read_line(&prompt)? {
+        Signal::Success(value) => Ok(Some(value.trim().to_owned())),
+        Signal::CtrlC | Signal::CtrlD => Ok(None),
+    }
+}
+
+/// Prompts the body of the message, until a line containing a single
+/// dot is entered.
+///
+/// Returns `None` if the user interrupted the composition.
+fn read_body(keybinds: &KeybindsStyle) -> Result<Option<String>> {
+    let mut reedline = Reedline::create()
+        .with_validator(Box::new(BodyValidator))
+        .with_edit_mode(edit_mode(keybinds));
+
+    let prompt = DefaultPrompt::new(
+        DefaultPromptSegment::Basic(String::from("Body")),
+        DefaultPromptSegment::Empty,
+    );
+
+    match reedline.read_line(&prompt)? {
+        Signal::Success(body) => {
+            let body = body.trim_end();
+            let body = body.strip_suffix('.').unwrap_or(body).trim_end();
+            Ok(Some(format!("{body}\n")))
+        }
+        Signal::CtrlC | Signal::CtrlD => Ok(None),
+    }
+}
+
+/// Composes a new message inline, by prompting headers then the body
+/// of the message. The recipient is not prompted if already given,
+/// it is written to the given output instead.
+///
+/// Returns `None` if the user interrupted the composition.
+pub async fn compose_inline(
+    config: Arc<AccountConfig>,
+    out: &mut dyn Write,
+    keybinds: &KeybindsStyle,
+    addresses: &[String],
+    to: Option<String>,
+) -> Result<Option<Template>> {
+    writeln!(
+        out,
+        "Press Tab to complete addresses, end the body with a single dot."
+    )?;
+
+    let to = match to {
+        Some(to) => {
+            writeln!(out, "To: {to}")?;
+            to
+        }
+        None => match read_header(keybinds, "To", addresses)? {
+            Some(to) => to,
+            None => return Ok(None),
+        },
+    };
+    let Some(cc) = read_header(keybinds, "Cc", addresses)? else {
+        return Ok(None);
+    };
+    let Some(subject) = read_header(keybinds, "Subject", &[])? else {
+        return Ok(None);
+    };
+    let Some(body) = read_body(keybinds)? else {
+        return Ok(None);
+    };
+
+    let mut headers = vec![("To", to)];
+
+    if !cc.is_empty() {
+        headers.push(("Cc", cc));
+    }
+
+    headers.push(("Subject", subject));
+
+    let tpl = Message::new_tpl_builder(config)
+        .with_headers(headers)
+        .with_body(body)
+        .build()
+        .await?;
+
+    Ok(Some(tpl))
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 
 use async_trait::async_trait;
 use email::{account::config::AccountConfig, config::Config};
@@ -7,8 +7,12 @@ use pimalaya_tui::{
     Result,
 };
 use serde::{Deserialize, Serialize};
+use shellexpand_utils::shellexpand_path;
 
-use crate::account::config::TomlAccountConfig;
+use crate::{
+    account::config::TomlAccountConfig, outbox::DEFAULT_OUTBOX_INTERVAL, theme::ThemeKind,
+    validation::DEFAULT_MAX_ATTACHMENT_SIZE,
+};
 
 /// The structure representation of the user TOML configuration file.
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
@@ -29,6 +33,13 @@ impl TomlConfig {
     pub fn repl_keybinds(&self) -> Option<&KeybindsStyle> {
         self.repl.as_ref().and_then(|c| c.keybinds())
     }
+
+    pub fn repl_read_mark_seen(&self) -> bool {
+        self.repl
+            .as_ref()
+            .and_then(|c| c.read_mark_seen())
+            .unwrap_or(true)
+    }
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
@@ -40,16 +51,137 @@ pub enum KeybindsStyle {
     Vi,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ReplConfig {
     pub keybinds: Option<KeybindsStyle>,
+    pub theme: Option<ThemeKind>,
+    pub editor: Option<String>,
+    pub read: Option<ReplReadConfig>,
+    pub send: Option<ReplSendConfig>,
+    pub contacts: Option<ReplContactsConfig>,
+    pub templates: Option<ReplTemplatesConfig>,
+    pub sync: Option<ReplSyncConfig>,
 }
 
 impl ReplConfig {
     pub fn keybinds(&self) -> Option<&KeybindsStyle> {
         self.keybinds.as_ref()
     }
+
+    pub fn theme(&self) -> ThemeKind {
+        self.theme.unwrap_or_default()
+    }
+
+    pub fn editor(&self) -> Option<&str> {
+        self.editor
+            .as_deref()
+            .filter(|editor| !editor.trim().is_empty())
+    }
+
+    pub fn read_mark_seen(&self) -> Option<bool> {
+        self.read.as_ref().and_then(|c| c.mark_seen)
+    }
+
+    pub fn send_max_attachment_size(&self) -> u64 {
+        self.send
+            .as_ref()
+            .and_then(|c| c.max_attachment_size)
+            .unwrap_or(DEFAULT_MAX_ATTACHMENT_SIZE)
+    }
+
+    pub fn send_delay(&self) -> Duration {
+        Duration::from_secs(self.send.as_ref().and_then(|c| c.delay).unwrap_or_default())
+    }
+
+    pub fn send_outbox_interval(&self) -> Duration {
+        let secs = self.send.as_ref().and_then(|c| c.outbox_interval);
+        // an interval of zero would flush the outbox in a busy loop
+        Duration::from_secs(secs.unwrap_or(DEFAULT_OUTBOX_INTERVAL).max(1))
+    }
+
+    pub fn contacts_vcard_dir(&self) -> Option<PathBuf> {
+        self.contacts
+            .as_ref()
+            .and_then(|c| c.vcard_dir.as_ref())
+            .map(shellexpand_path)
+    }
+
+    pub fn templates_dir(&self) -> Option<PathBuf> {
+        self.templates
+            .as_ref()
+            .and_then(|c| c.dir.as_ref())
+            .map(shellexpand_path)
+    }
+
+    pub fn sync_dir(&self) -> Option<PathBuf> {
+        self.sync
+            .as_ref()
+            .and_then(|c| c.dir.as_ref())
+            .map(shellexpand_path)
+    }
+
+    pub fn sync_interval(&self) -> Option<Duration> {
+        self.sync
+            .as_ref()
+            .and_then(|c| c.interval)
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReplReadConfig {
+    /// Add the Seen flag to messages once they have been read.
+    ///
+    /// Messages can still be read without altering their flags using
+    /// the `peek` command. Defaults to true.
+    pub mark_seen: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReplSendConfig {
+    /// Maximum size of attachments in bytes, above which a warning is
+    /// shown before sending. Defaults to 25 MiB.
+    pub max_attachment_size: Option<u64>,
+    /// Delay in seconds before sending messages, during which the
+    /// sending can be cancelled with the `undo` command. Defaults to
+    /// 0, which sends messages straight away.
+    pub delay: Option<u64>,
+    /// Interval in seconds between two attempts to send the messages
+    /// of the outbox. Defaults to 60.
+    pub outbox_interval: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReplContactsConfig {
+    /// Directory of vCard files used to seed the address book at
+    /// startup.
+    pub vcard_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReplTemplatesConfig {
+    /// Directory of named templates used by the `--template` option.
+    /// Defaults to the `himalaya/templates` directory of the XDG
+    /// config directory.
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReplSyncConfig {
+    /// Root directory of the local Maildir caches, one sub-directory
+    /// per account. Defaults to the `himalaya/sync` directory of the
+    /// XDG data directory.
+    pub dir: Option<PathBuf>,
+    /// Interval in seconds between two background synchronizations.
+    /// Background synchronization is disabled by default.
+    pub interval: Option<u64>,
 }
 
 impl From<TomlConfig> for Config {
@@ -116,14 +248,16 @@ impl pimalaya_tui::config::toml::TomlConfig for TomlConfig {
                             signature: config.signature,
                             signature_delim: config.signature_delim,
                             downloads_dir: config.downloads_dir,
-                            backend: config.backend,
+                            backend: config.backend.map(Into::into),
+                            backends: None,
+                            identities: None,
 
                             #[cfg(feature = "pgp")]
                             pgp: config.pgp,
 
                             folder: config.folder,
-                            envelope: config.envelope,
-                            message: config.message,
+                            envelope: config.envelope.map(Into::into),
+                            message: config.message.map(Into::into),
                             template: config.template,
 
                             #[cfg(feature = "imap")]
@@ -136,6 +270,8 @@ impl pimalaya_tui::config::toml::TomlConfig for TomlConfig {
                             smtp: config.smtp,
                             #[cfg(feature = "sendmail")]
                             sendmail: config.sendmail,
+                            #[cfg(feature = "memory")]
+                            memory: None,
                         },
                     )
                 })
@@ -0,0 +1,317 @@
+//! Address book module.
+//!
+//! The address book is a local list of contacts harvested from the
+//! envelopes and messages seen in the REPL, optionally seeded from a
+//! directory of vCard files. It is mostly used to complete
+//! recipients when composing messages.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use comfy_table::{presets, Cell, ContentArrangement, Row, Table};
+use dirs::data_dir;
+use email::{account::config::AccountConfig, message::Message};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::envelope::Mailbox;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: Option<String>,
+    pub addr: String,
+}
+
+impl Contact {
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+
+        self.addr.to_lowercase().contains(&query)
+            || self
+                .name
+                .as_ref()
+                .map(|name| name.to_lowercase().contains(&query))
+                .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Contact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "\"{}\" <{}>", name.replace('"', ""), self.addr),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    path: PathBuf,
+    contacts: BTreeMap<String, Contact>,
+    changed: bool,
+}
+
+impl AddressBook {
+    pub fn new(account_config: &AccountConfig) -> Result<Self> {
+        let path = data_dir()
+            .ok_or(eyre!("cannot get XDG data directory"))?
+            .join("himalaya")
+            .join("contacts")
+            .join(&account_config.name)
+            .with_extension("json");
+
+        Self::from_path(path)
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+
+        let contacts = if path.exists() {
+            let contacts =
+                fs::read(&path).with_context(|| format!("cannot read address book at {path:?}"))?;
+            serde_json::from_slice::<Vec<Contact>>(&contacts)
+                .with_context(|| format!("cannot parse address book at {path:?}"))?
+                .into_iter()
+                .map(|contact| (contact.addr.to_lowercase(), contact))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path,
+            contacts,
+            changed: false,
+        })
+    }
+
+    /// Saves the address book on the filesystem, only if it changed
+    /// since the last save.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let path = &self.path;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("cannot create address book directory at {dir:?}"))?;
+        }
+
+        let contacts = serde_json::to_vec_pretty(&Vec::from_iter(self.contacts.values()))?;
+        fs::write(path, contacts)
+            .with_context(|| format!("cannot write address book at {path:?}"))?;
+
+        debug!("saved {} contacts to {path:?}", self.contacts.len());
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Adds the given contact to the address book, or updates its
+    /// name if the address is already known.
+    pub fn add(&mut self, addr: impl ToString, name: Option<String>) {
+        let addr = addr.to_string();
+        let name = name.filter(|name| !name.trim().is_empty());
+        let key = addr.to_lowercase();
+
+        match self.contacts.get_mut(&key) {
+            Some(contact) if name.is_some() && contact.name != name => {
+                contact.name = name;
+                self.changed = true;
+            }
+            Some(_) => (),
+            None => {
+                self.contacts.insert(key, Contact { name, addr });
+                self.changed = true;
+            }
+        }
+    }
+
+    /// Adds the given mailbox to the address book, without
+    /// overriding the name of known contacts.
+    pub fn harvest(&mut self, mailbox: &Mailbox) {
+        let addr = mailbox.addr.trim();
+
+        if !addr.contains('@') {
+            return;
+        }
+
+        match self.contacts.get_mut(&addr.to_lowercase()) {
+            Some(contact) if contact.name.is_none() && mailbox.name.is_some() => {
+                contact.name = mailbox.name.clone();
+                self.changed = true;
+            }
+            Some(_) => (),
+            None => self.add(addr, mailbox.name.clone()),
+        }
+    }
+
+    /// Harvests senders and recipients of the given message.
+    pub fn harvest_message(&mut self, message: &Message) -> Result<()> {
+        let message = message.parsed()?;
+
+        let addrs = [message.from(), message.to(), message.cc()]
+            .into_iter()
+            .flatten()
+            .flat_map(|addrs| addrs.iter());
+
+        for addr in addrs {
+            if let Some(email) = addr.address() {
+                self.harvest(&Mailbox {
+                    name: addr.name().map(ToOwned::to_owned),
+                    addr: email.to_owned(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, addr: &str) -> Option<Contact> {
+        let contact = self.contacts.remove(&addr.to_lowercase());
+        self.changed |= contact.is_some();
+        contact
+    }
+
+    /// Searches contacts whose address or name contain the given
+    /// query, case-insensitively.
+    pub fn search(&self, query: &str) -> Vec<&Contact> {
+        self.contacts
+            .values()
+            .filter(|contact| contact.matches(query))
+            .collect()
+    }
+
+    /// Lists all known addresses, used for completion.
+    pub fn addresses(&self) -> Vec<String> {
+        self.contacts
+            .values()
+            .map(|contact| contact.addr.clone())
+            .collect()
+    }
+
+    /// Imports contacts from all vCard files of the given directory.
+    ///
+    /// Returns the number of imported addresses.
+    pub fn import_vcard_dir(&mut self, dir: impl AsRef<Path>) -> Result<usize> {
+        let dir = dir.as_ref();
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("cannot read vCard directory at {dir:?}"))?;
+
+        let mut count = 0;
+
+        for entry in entries {
+            let path = entry?.path();
+
+            let is_vcard = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| ext.eq_ignore_ascii_case("vcf") || ext.eq_ignore_ascii_case("vcard"))
+                .unwrap_or_default();
+
+            if !is_vcard {
+                continue;
+            }
+
+            let vcard = fs::read_to_string(&path)
+                .with_context(|| format!("cannot read vCard at {path:?}"))?;
+
+            for (name, addrs) in parse_vcards(&vcard) {
+                for addr in addrs {
+                    self.add(addr, name.clone());
+                    count += 1;
+                }
+            }
+        }
+
+        debug!("imported {count} addresses from {dir:?}");
+        Ok(count)
+    }
+}
+
+/// Parses the formatted name and email addresses of all vCards
+/// contained in the given content.
+fn parse_vcards(content: &str) -> Vec<(Option<String>, Vec<String>)> {
+    // unfold lines, as defined in RFC6350 section 3.2
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(folded), Some(last)) => last.push_str(folded),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    let mut cards = Vec::new();
+    let mut name = None;
+    let mut addrs = Vec::new();
+
+    for line in lines {
+        let Some((key, val)) = line.split_once(':') else {
+            continue;
+        };
+
+        let key = key.split(';').next().unwrap_or_default().to_uppercase();
+        // drop the optional group prefix, like in `item1.EMAIL`
+        let key = key.rsplit('.').next().unwrap_or_default();
+        let val = val.trim();
+
+        match key {
+            "BEGIN" if val.eq_ignore_ascii_case("vcard") => {
+                name = None;
+                addrs = Vec::new();
+            }
+            "FN" if !val.is_empty() => {
+                name = Some(val.replace("\\,", ",").replace("\\;", ";"));
+            }
+            "EMAIL" if val.contains('@') => {
+                addrs.push(val.to_owned());
+            }
+            "END" if val.eq_ignore_ascii_case("vcard") => {
+                cards.push((name.take(), std::mem::take(&mut addrs)));
+            }
+            _ => (),
+        }
+    }
+
+    cards
+}
+
+pub struct ContactsTable<'a>(Vec<&'a Contact>);
+
+impl<'a> From<Vec<&'a Contact>> for ContactsTable<'a> {
+    fn from(contacts: Vec<&'a Contact>) -> Self {
+        Self(contacts)
+    }
+}
+
+impl fmt::Display for ContactsTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+
+        table
+            .load_preset(presets::ASCII_MARKDOWN)
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_header(Row::from([Cell::new("NAME"), Cell::new("ADDRESS")]))
+            .add_rows(self.0.iter().map(|contact| {
+                let mut row = Row::new();
+                row.max_height(1);
+                row.add_cell(Cell::new(contact.name.as_deref().unwrap_or_default()))
+                    .add_cell(Cell::new(&contact.addr));
+                row
+            }));
+
+        writeln!(f)?;
+        write!(f, "{table}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+//! Local drafts module.
+//!
+//! Local drafts are templates saved on the filesystem, one file per
+//! draft, inside the drafts directory of the current account. Each
+//! template comes with a small metadata file keeping track of the
+//! origin of the draft (new message, reply or forward).
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use comfy_table::{presets, Cell, ContentArrangement, Row, Table};
+use dirs::data_dir;
+use email::{account::config::AccountConfig, template::Template};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use uuid::Uuid;
+
+/// The origin of a local draft.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum DraftOrigin {
+    New,
+    Reply { folder: String, id: String },
+    Forward { folder: String, id: String },
+    RemoteDraft { id: String },
+}
+
+impl fmt::Display for DraftOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::New => write!(f, "new message"),
+            Self::Reply { folder, id } => write!(f, "reply to {id} ({folder})"),
+            Self::Forward { folder, id } => write!(f, "forward of {id} ({folder})"),
+            Self::RemoteDraft { id } => write!(f, "remote draft {id}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct LocalDraftMetadata {
+    origin: DraftOrigin,
+    created_at: SystemTime,
+}
+
+/// A template saved locally, waiting to be edited, sent or
+/// discarded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LocalDraft {
+    pub id: String,
+    pub origin: DraftOrigin,
+    pub created_at: SystemTime,
+    pub path: PathBuf,
+}
+
+impl LocalDraft {
+    fn metadata_path(&self) -> PathBuf {
+        self.path.with_extension("json")
+    }
+
+    pub fn read(&self) -> Result<Template> {
+        let path = &self.path;
+        let content =
+            fs::read_to_string(path).context(format!("cannot read local draft at {path:?}"))?;
+        Ok(content.into())
+    }
+
+    pub fn write(&self, tpl: &Template) -> Result<()> {
+        let path = &self.path;
+        fs::write(path, tpl.as_bytes()).context(format!("cannot write local draft at {path:?}"))
+    }
+
+    /// Reads the value of the subject header of the draft, if any.
+    pub fn subject(&self) -> Option<String> {
+        let tpl = fs::read_to_string(&self.path).ok()?;
+
+        tpl.lines()
+            .take_while(|line| !line.trim().is_empty())
+            .find_map(|line| {
+                let (key, val) = line.split_once(':')?;
+                if key.trim().eq_ignore_ascii_case("subject") {
+                    Some(val.trim().to_owned())
+                } else {
+                    None
+                }
+            })
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        debug!("removing local draft {}", self.id);
+
+        for path in [&self.path, &self.metadata_path()] {
+            if path.exists() {
+                fs::remove_file(path).context(format!("cannot remove local draft at {path:?}"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The local drafts directory of an account.
+#[derive(Clone, Debug)]
+pub struct LocalDrafts {
+    dir: PathBuf,
+}
+
+impl LocalDrafts {
+    pub fn new(account_config: &AccountConfig) -> Result<Self> {
+        let dir = data_dir()
+            .ok_or(eyre!("cannot get XDG data directory"))?
+            .join("himalaya")
+            .join("drafts")
+            .join(&account_config.name);
+
+        Self::from_dir(dir)
+    }
+
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("cannot create local drafts directory at {dir:?}"))?;
+
+        Ok(Self { dir })
+    }
+
+    /// Creates a new empty local draft with the given origin.
+    pub fn create(&self, origin: DraftOrigin) -> Result<LocalDraft> {
+        let id = Uuid::new_v4().to_string()[..8].to_owned();
+
+        let draft = LocalDraft {
+            path: self.dir.join(&id).with_extension("eml"),
+            id,
+            origin,
+            created_at: SystemTime::now(),
+        };
+
+        let metadata = LocalDraftMetadata {
+            origin: draft.origin.clone(),
+            created_at: draft.created_at,
+        };
+        let metadata = serde_json::to_vec_pretty(&metadata)?;
+        let path = draft.metadata_path();
+        fs::write(&path, metadata).context(format!("cannot write local draft at {path:?}"))?;
+
+        debug!("created local draft {} for {}", draft.id, draft.origin);
+        Ok(draft)
+    }
+
+    /// Lists local drafts, from the oldest to the most recent one.
+    pub fn list(&self) -> Result<Vec<LocalDraft>> {
+        let entries = fs::read_dir(&self.dir)
+            .with_context(|| format!("cannot read local drafts directory at {:?}", self.dir))?;
+
+        let mut drafts = Vec::new();
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let metadata =
+                fs::read(&path).with_context(|| format!("cannot read local draft at {path:?}"))?;
+            let metadata: LocalDraftMetadata = match serde_json::from_slice(&metadata) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    debug!("skipping invalid local draft at {path:?}: {err}");
+                    continue;
+                }
+            };
+
+            drafts.push(LocalDraft {
+                id: id.to_owned(),
+                origin: metadata.origin,
+                created_at: metadata.created_at,
+                path: path.with_extension("eml"),
+            })
+        }
+
+        drafts.sort_by_key(|draft| draft.created_at);
+        Ok(drafts)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<LocalDraft>> {
+        Ok(self.list()?.into_iter().find(|draft| draft.id == id))
+    }
+
+    /// Finds the most recent local draft having the given origin.
+    pub fn find_by_origin(&self, origin: &DraftOrigin) -> Result<Option<LocalDraft>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .rev()
+            .find(|draft| &draft.origin == origin))
+    }
+}
+
+pub struct LocalDraftsTable(Vec<LocalDraft>);
+
+impl From<Vec<LocalDraft>> for LocalDraftsTable {
+    fn from(drafts: Vec<LocalDraft>) -> Self {
+        Self(drafts)
+    }
+}
+
+impl fmt::Display for LocalDraftsTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+
+        table
+            .load_preset(presets::ASCII_MARKDOWN)
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_header(Row::from([
+                Cell::new("ID"),
+                Cell::new("ORIGIN"),
+                Cell::new("SUBJECT"),
+            ]))
+            .add_rows(self.0.iter().map(|draft| {
+                let mut row = Row::new();
+                row.max_height(1);
+                row.add_cell(Cell::new(&draft.id))
+                    .add_cell(Cell::new(&draft.origin))
+                    .add_cell(Cell::new(draft.subject().unwrap_or_default()));
+                row
+            }));
+
+        writeln!(f)?;
+        write!(f, "{table}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
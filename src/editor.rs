@@ -1,20 +1,26 @@
-use std::{env, fmt, fs, sync::Arc};
+use std::{env, fmt, io::Write, path::Path, sync::Arc};
 
 use color_eyre::{eyre::Context, Result};
 use email::{
     account::config::AccountConfig,
+    envelope::Id,
     flag::{Flag, Flags},
     folder::DRAFTS,
-    local_draft_path,
-    message::{add::AddMessage, send::SendMessageThenSaveCopy},
-    remove_local_draft,
+    message::{add::AddMessage, delete::DeleteMessages},
     template::Template,
 };
 use mml::MmlCompilerBuilder;
-use pimalaya_tui::prompt;
 use process::SingleCommand;
 
-use crate::backend::Backend;
+use crate::{
+    backend::Backend,
+    config::ReplConfig,
+    draft::{DraftOrigin, LocalDraft, LocalDrafts},
+    input::{self, Input, Io},
+    outbox, pager, pgp, preview,
+    queue::SendQueue,
+    validation::{self, Warning},
+};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum PreEditChoice {
@@ -43,11 +49,11 @@ static PRE_EDIT_CHOICES: [PreEditChoice; 3] = [
     PreEditChoice::Quit,
 ];
 
-pub fn pre_edit() -> Result<PreEditChoice> {
-    let user_choice = prompt::item(
+pub fn pre_edit(input: &mut dyn Input) -> Result<PreEditChoice> {
+    let user_choice = input::item(
+        input,
         "A draft was found, what would you like to do with it?",
         &PRE_EDIT_CHOICES,
-        None,
     )?;
 
     Ok(user_choice.clone())
@@ -56,6 +62,9 @@ pub fn pre_edit() -> Result<PreEditChoice> {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum PostEditChoice {
     Send,
+    Schedule,
+    Preview,
+    Secure,
     Edit,
     LocalDraft,
     RemoteDraft,
@@ -69,6 +78,9 @@ impl fmt::Display for PostEditChoice {
             "{}",
             match self {
                 Self::Send => "Send it",
+                Self::Schedule => "Send it later",
+                Self::Preview => "Preview it",
+                Self::Secure => "Sign or encrypt it",
                 Self::Edit => "Edit it again",
                 Self::LocalDraft => "Save it as local draft",
                 Self::RemoteDraft => "Save it as remote draft",
@@ -78,88 +90,266 @@ impl fmt::Display for PostEditChoice {
     }
 }
 
-static POST_EDIT_CHOICES: [PostEditChoice; 5] = [
+static POST_EDIT_CHOICES: [PostEditChoice; 8] = [
     PostEditChoice::Send,
+    PostEditChoice::Schedule,
+    PostEditChoice::Preview,
+    PostEditChoice::Secure,
     PostEditChoice::Edit,
     PostEditChoice::LocalDraft,
     PostEditChoice::RemoteDraft,
     PostEditChoice::Discard,
 ];
 
-pub fn post_edit() -> Result<PostEditChoice> {
-    let user_choice = prompt::item(
+pub fn post_edit(input: &mut dyn Input) -> Result<PostEditChoice> {
+    let user_choice = input::item(
+        input,
         "What would you like to do with this message?",
         &POST_EDIT_CHOICES,
-        None,
     )?;
 
     Ok(user_choice.clone())
 }
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PreSendChoice {
+    Send,
+    Edit,
+    Back,
+}
+
+impl fmt::Display for PreSendChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Send => "Send it anyway",
+                Self::Edit => "Edit it again",
+                Self::Back => "Go back",
+            }
+        )
+    }
+}
+
+static PRE_SEND_CHOICES: [PreSendChoice; 3] = [
+    PreSendChoice::Send,
+    PreSendChoice::Edit,
+    PreSendChoice::Back,
+];
+
+pub fn pre_send(io: &mut Io<'_>, warnings: &[Warning]) -> Result<PreSendChoice> {
+    writeln!(io.out, "This message may not be ready to be sent:")?;
+
+    for warning in warnings {
+        writeln!(io.out, " - {warning}")?;
+    }
+
+    let user_choice = input::item(
+        io.input,
+        "What would you like to do with this message?",
+        &PRE_SEND_CHOICES,
+    )?;
+
+    Ok(user_choice.clone())
+}
+
 pub async fn edit_tpl_with_editor(
-    #[cfg_attr(not(feature = "pgp"), allow(unused_variables))] config: Arc<AccountConfig>,
+    config: Arc<AccountConfig>,
     backend: &Backend,
+    queue: &SendQueue,
+    repl_config: &ReplConfig,
+    io: &mut Io<'_>,
+    origin: DraftOrigin,
     mut tpl: Template,
 ) -> Result<()> {
-    let draft = local_draft_path();
-    if draft.exists() {
-        loop {
-            match pre_edit() {
+    let drafts = LocalDrafts::new(&config)?;
+
+    // new messages never conflict with existing drafts, replies and
+    // forwards only conflict with drafts sharing the same origin
+    let existing_draft = match origin {
+        DraftOrigin::New => None,
+        ref origin => drafts.find_by_origin(origin)?,
+    };
+
+    let draft = match existing_draft {
+        Some(draft) => loop {
+            match pre_edit(io.input) {
                 Ok(choice) => match choice {
                     PreEditChoice::Edit => {
-                        tpl = open_with_local_draft().await?;
-                        break;
+                        tpl = open_with_local_draft(repl_config, &draft).await?;
+                        break draft;
                     }
                     PreEditChoice::Discard => {
-                        tpl = open_with_tpl(tpl).await?;
-                        break;
+                        tpl = open_with_tpl(repl_config, &draft, tpl).await?;
+                        break draft;
                     }
                     PreEditChoice::Quit => return Ok(()),
                 },
                 Err(err) => {
-                    println!("{}", err);
+                    writeln!(io.err, "{err}")?;
                     continue;
                 }
             }
+        },
+        None => {
+            let draft = drafts.create(origin)?;
+            tpl = open_with_tpl(repl_config, &draft, tpl).await?;
+            draft
         }
-    } else {
-        tpl = open_with_tpl(tpl).await?;
-    }
+    };
+
+    handle_post_edit(config, backend, queue, repl_config, io, &draft, tpl).await
+}
 
+/// Saves the given template as a new local draft then continues with
+/// the post-edit flow, without opening the editor first.
+pub async fn edit_tpl_inline(
+    config: Arc<AccountConfig>,
+    backend: &Backend,
+    queue: &SendQueue,
+    repl_config: &ReplConfig,
+    io: &mut Io<'_>,
+    origin: DraftOrigin,
+    tpl: Template,
+) -> Result<()> {
+    let draft = LocalDrafts::new(&config)?.create(origin)?;
+    draft.write(&tpl)?;
+    handle_post_edit(config, backend, queue, repl_config, io, &draft, tpl).await
+}
+
+pub async fn edit_local_draft(
+    config: Arc<AccountConfig>,
+    backend: &Backend,
+    queue: &SendQueue,
+    repl_config: &ReplConfig,
+    io: &mut Io<'_>,
+    draft: &LocalDraft,
+) -> Result<()> {
+    let tpl = open_with_local_draft(repl_config, draft).await?;
+    handle_post_edit(config, backend, queue, repl_config, io, draft, tpl).await
+}
+
+async fn handle_post_edit(
+    config: Arc<AccountConfig>,
+    backend: &Backend,
+    queue: &SendQueue,
+    repl_config: &ReplConfig,
+    io: &mut Io<'_>,
+    draft: &LocalDraft,
+    mut tpl: Template,
+) -> Result<()> {
     loop {
-        match post_edit() {
-            Ok(PostEditChoice::Send) => {
-                println!("Sending email…");
+        match post_edit(io.input) {
+            Ok(choice @ (PostEditChoice::Send | PostEditChoice::Schedule)) => {
+                let warnings =
+                    validation::validate_tpl(tpl.as_str(), repl_config.send_max_attachment_size());
 
-                #[allow(unused_mut)]
-                let mut compiler = MmlCompilerBuilder::new();
+                if !warnings.is_empty() {
+                    match pre_send(io, &warnings) {
+                        Ok(PreSendChoice::Send) => (),
+                        Ok(PreSendChoice::Edit) => {
+                            tpl = open_with_tpl(repl_config, draft, tpl).await?;
+                            continue;
+                        }
+                        Ok(PreSendChoice::Back) => continue,
+                        Err(err) => {
+                            writeln!(io.err, "{err}")?;
+                            continue;
+                        }
+                    }
+                }
+
+                if pgp::is_encrypted_tpl(&tpl) {
+                    match pgp::missing_keys(&config, &tpl).await {
+                        Ok(missing) if missing.is_empty() => (),
+                        Ok(missing) => {
+                            writeln!(
+                                io.err,
+                                "Cannot encrypt the message, no public key found for:"
+                            )?;
+
+                            for recipient in missing {
+                                writeln!(io.err, " - {recipient}")?;
+                            }
+
+                            continue;
+                        }
+                        Err(err) => {
+                            writeln!(io.err, "{err}")?;
+                            continue;
+                        }
+                    }
+                }
+
+                if choice == PostEditChoice::Schedule {
+                    let send_at = io.input.text("Send at (YYYY-MM-DDTHH:MM):", None)?;
+                    let send_at = match outbox::parse_send_at(&send_at) {
+                        Ok(send_at) => send_at,
+                        Err(err) => {
+                            writeln!(io.err, "{err}")?;
+                            continue;
+                        }
+                    };
 
-                #[cfg(feature = "pgp")]
-                compiler.set_some_pgp(config.pgp.clone());
+                    let email = compile_tpl(&config, &tpl).await?;
+                    let message = queue.schedule(draft, &email, send_at).await?;
+                    writeln!(io.out, "Email successfully scheduled as {}", message.id)?;
+                    break;
+                }
 
-                let email = compiler.build(tpl.as_str())?.compile().await?.into_vec()?;
+                let email = compile_tpl(&config, &tpl).await?;
+                let delay = queue.delay();
 
-                backend.send_message_then_save_copy(&email).await?;
+                if !delay.is_zero() {
+                    queue.push(draft.clone(), email);
+                    writeln!(
+                        io.out,
+                        "Email will be sent in {}s, type `undo` to cancel",
+                        delay.as_secs()
+                    )?;
+                    break;
+                }
 
-                remove_local_draft()?;
-                println!("Done!");
+                writeln!(io.out, "Sending email…")?;
+                writeln!(io.out, "{}", queue.send(draft, &email).await?)?;
                 break;
             }
             Ok(PostEditChoice::Edit) => {
-                tpl = open_with_tpl(tpl).await?;
+                tpl = open_with_tpl(repl_config, draft, tpl).await?;
+                continue;
+            }
+            Ok(PostEditChoice::Secure) => {
+                let (sign, encrypt) = pgp::tpl_security(&tpl);
+                let sign = io.input.bool("Sign the message?", sign)?;
+                let encrypt = io.input.bool("Encrypt the message?", encrypt)?;
+
+                tpl = pgp::wrap_tpl(&tpl, sign, encrypt).into();
+                draft.write(&tpl)?;
                 continue;
             }
             Ok(PostEditChoice::LocalDraft) => {
-                println!("Email successfully saved locally");
+                writeln!(
+                    io.out,
+                    "Email successfully saved locally as draft {}",
+                    draft.id
+                )?;
                 break;
             }
-            Ok(PostEditChoice::RemoteDraft) => {
-                #[allow(unused_mut)]
-                let mut compiler = MmlCompilerBuilder::new();
+            Ok(PostEditChoice::Preview) => {
+                let email = compile_tpl(&config, &tpl).await?;
 
-                #[cfg(feature = "pgp")]
-                compiler.set_some_pgp(config.pgp.clone());
+                writeln!(io.out)?;
+                writeln!(io.out, "{}", preview::mime_tree(&email)?)?;
 
-                let email = compiler.build(tpl.as_str())?.compile().await?.into_vec()?;
+                if io.input.bool("Show the raw message?", false)? {
+                    pager::page(io.out, &String::from_utf8_lossy(&email))?;
+                }
+
+                continue;
+            }
+            Ok(PostEditChoice::RemoteDraft) => {
+                let email = compile_tpl(&config, &tpl).await?;
 
                 backend
                     .add_message_with_flags(
@@ -168,16 +358,17 @@ pub async fn edit_tpl_with_editor(
                         &Flags::from_iter([Flag::Seen, Flag::Draft]),
                     )
                     .await?;
-                remove_local_draft()?;
-                println!("Email successfully saved to drafts");
+                remove_remote_draft(backend, draft).await?;
+                draft.remove()?;
+                writeln!(io.out, "Email successfully saved to drafts")?;
                 break;
             }
             Ok(PostEditChoice::Discard) => {
-                remove_local_draft()?;
+                draft.remove()?;
                 break;
             }
             Err(err) => {
-                println!("{}", err);
+                writeln!(io.err, "{err}")?;
                 continue;
             }
         }
@@ -186,30 +377,140 @@ pub async fn edit_tpl_with_editor(
     Ok(())
 }
 
-pub async fn open_with_local_draft() -> Result<Template> {
-    let path = local_draft_path();
-    let content =
-        fs::read_to_string(&path).context(format!("cannot read local draft at {:?}", path))?;
-    open_with_tpl(content.into()).await
+/// Compiles the given MML template into a raw MIME message.
+pub async fn compile_tpl(
+    #[cfg_attr(not(feature = "pgp"), allow(unused_variables))] config: &AccountConfig,
+    tpl: &Template,
+) -> Result<Vec<u8>> {
+    #[allow(unused_mut)]
+    let mut compiler = MmlCompilerBuilder::new();
+
+    #[cfg(feature = "pgp")]
+    compiler.set_some_pgp(config.pgp.clone());
+
+    let email = compiler.build(tpl.as_str())?.compile().await?.into_vec()?;
+
+    Ok(email)
+}
+
+/// Removes the previous copy of the draft from the drafts folder, if
+/// the local draft originates from there.
+pub async fn remove_remote_draft(backend: &Backend, draft: &LocalDraft) -> Result<()> {
+    if let DraftOrigin::RemoteDraft { id } = &draft.origin {
+        backend.delete_messages(DRAFTS, &Id::single(id)).await?;
+    }
+
+    Ok(())
 }
 
-pub async fn open_with_tpl(tpl: Template) -> Result<Template> {
-    let path = local_draft_path();
+pub async fn open_with_local_draft(config: &ReplConfig, draft: &LocalDraft) -> Result<Template> {
+    let tpl = draft.read()?;
+    open_with_tpl(config, draft, tpl).await
+}
 
+pub async fn open_with_tpl(
+    config: &ReplConfig,
+    draft: &LocalDraft,
+    tpl: Template,
+) -> Result<Template> {
     tracing::debug!("create draft");
-    fs::write(&path, tpl.as_bytes()).context(format!("cannot write local draft at {:?}", path))?;
+    draft.write(&tpl)?;
 
     tracing::debug!("open editor");
-    let editor = env::var("EDITOR").context("cannot get editor from env var")?;
-    SingleCommand::from(format!("{editor} {}", &path.to_string_lossy()))
+    let editor = resolve_editor(config);
+    let cmd = editor_command(&editor, &draft.path, body_line(&tpl))?;
+    tracing::debug!("editor command: {cmd}");
+
+    SingleCommand::from(cmd)
         .with_output_piped(false)
         .run()
         .await
-        .context("cannot launch editor")?;
+        .context(format!("cannot launch editor {editor}"))?;
 
     tracing::debug!("read draft");
-    let content =
-        fs::read_to_string(&path).context(format!("cannot read local draft at {:?}", path))?;
+    draft.read()
+}
+
+#[cfg(windows)]
+static DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+static DEFAULT_EDITOR: &str = "vi";
+
+/// Editors known to accept a `+<line>` argument placing the cursor at
+/// the given line.
+static LINE_AWARE_EDITORS: [&str; 9] = [
+    "vi",
+    "vim",
+    "nvim",
+    "gvim",
+    "nano",
+    "emacs",
+    "emacsclient",
+    "kak",
+    "micro",
+];
+
+/// Resolves the editor command from the REPL configuration, then from
+/// the `VISUAL` and `EDITOR` environment variables, falling back to
+/// the default system editor.
+pub fn resolve_editor(config: &ReplConfig) -> String {
+    let from_env = |key| {
+        env::var(key)
+            .ok()
+            .filter(|editor| !editor.trim().is_empty())
+    };
+
+    config
+        .editor()
+        .map(ToOwned::to_owned)
+        .or_else(|| from_env("VISUAL"))
+        .or_else(|| from_env("EDITOR"))
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_owned())
+}
+
+/// Builds the shell command opening the given path with the given
+/// editor.
+///
+/// The editor may contain the `{path}` and `{line}` placeholders,
+/// otherwise the quoted path is appended at the end of the command.
+/// When no placeholder is given and the editor is known to support
+/// it, the cursor is placed at the given line using `+<line>`.
+pub fn editor_command(editor: &str, path: &Path, line: usize) -> Result<String> {
+    let path = path.to_string_lossy();
+    let path = shlex::try_quote(&path).context("cannot quote draft path")?;
+
+    if editor.contains("{path}") {
+        let cmd = editor
+            .replace("{line}", &line.to_string())
+            .replace("{path}", &path);
+        return Ok(cmd);
+    }
+
+    if editor.contains("{line}") {
+        let cmd = editor.replace("{line}", &line.to_string());
+        return Ok(format!("{cmd} {path}"));
+    }
+
+    let program = shlex::split(editor)
+        .and_then(|args| args.into_iter().next())
+        .unwrap_or_default();
+    let program = Path::new(&program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    if LINE_AWARE_EDITORS.contains(&program) {
+        Ok(format!("{editor} +{line} {path}"))
+    } else {
+        Ok(format!("{editor} {path}"))
+    }
+}
 
-    Ok(content.into())
+/// Finds the line of the beginning of the template body, which is the
+/// line following the empty line separating headers from the body.
+fn body_line(tpl: &str) -> usize {
+    tpl.lines()
+        .position(|line| line.trim().is_empty())
+        .map(|pos| pos + 2)
+        .unwrap_or(1)
 }
@@ -1,12 +1,27 @@
-use std::{collections::HashSet, fmt, ops::Deref, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
 
+use chrono::{DateTime, FixedOffset, Local};
 use color_eyre::Result;
-use comfy_table::{presets, Attribute, Cell, ContentArrangement, Row, Table};
+use comfy_table::{
+    presets, Attribute, Cell, CellAlignment, ColumnConstraint, ContentArrangement, Row, Table,
+    Width,
+};
 use crossterm::style::Color;
 use email::account::config::AccountConfig;
 use serde::{Deserialize, Serialize};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::{id_mapper::IdMapper, map_color};
+use crate::{
+    id_mapper::IdMapper,
+    preview,
+    theme::{table_color, Theme},
+};
 
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct Mailbox {
@@ -21,64 +36,54 @@ pub struct Envelope {
     pub subject: String,
     pub from: Mailbox,
     pub to: Mailbox,
-    pub date: String,
+    pub date: DateTime<FixedOffset>,
     pub has_attachment: bool,
+    pub folder: String,
+    pub account: String,
+    /// Size of the raw message in bytes, only fetched when the size
+    /// column is shown.
+    pub size: Option<usize>,
+    /// Number of ancestors of the envelope among the listed ones.
+    pub depth: usize,
 }
 
 impl Envelope {
-    fn to_row(&self, config: &ListEnvelopesTableConfig) -> Row {
-        let mut all_attributes = vec![];
-
-        let unseen = !self.flags.contains(&Flag::Seen);
-        if unseen {
-            all_attributes.push(Attribute::Bold)
-        }
+    fn is_unseen(&self) -> bool {
+        !self.flags.contains(&Flag::Seen)
+    }
 
-        let flags = {
-            let mut flags = String::new();
+    fn flags_column(&self, config: &ListEnvelopesTableConfig) -> String {
+        let mut flags = String::new();
 
-            flags.push(config.flagged_char(self.flags.contains(&Flag::Flagged)));
-            flags.push(config.unseen_char(unseen));
-            flags.push(config.attachment_char(self.has_attachment));
-            flags.push(config.replied_char(self.flags.contains(&Flag::Answered)));
+        flags.push(config.flagged_char(self.flags.contains(&Flag::Flagged)));
+        flags.push(config.unseen_char(self.is_unseen()));
+        flags.push(config.attachment_char(self.has_attachment));
+        flags.push(config.replied_char(self.flags.contains(&Flag::Answered)));
 
-            flags
-        };
-
-        let mut row = Row::new();
-        row.max_height(1);
+        flags
+    }
 
-        row.add_cell(
-            Cell::new(&self.id)
-                .add_attributes(all_attributes.clone())
-                .fg(config.id_color()),
-        )
-        .add_cell(
-            Cell::new(flags)
-                .add_attributes(all_attributes.clone())
-                .fg(config.flags_color()),
-        )
-        .add_cell(
-            Cell::new(&self.subject)
-                .add_attributes(all_attributes.clone())
-                .fg(config.subject_color()),
-        )
-        .add_cell(
-            Cell::new(if let Some(name) = &self.from.name {
-                name
-            } else {
-                &self.from.addr
-            })
-            .add_attributes(all_attributes.clone())
-            .fg(config.sender_color()),
-        )
-        .add_cell(
-            Cell::new(&self.date)
-                .add_attributes(all_attributes)
-                .fg(config.date_color()),
-        );
+    fn cell(&self, kind: ColumnKind, table: &EnvelopesTable) -> String {
+        match kind {
+            ColumnKind::Id => self.id.clone(),
+            ColumnKind::Flags => self.flags_column(&table.config),
+            ColumnKind::Subject => self.subject.clone(),
+            ColumnKind::From => self.from.to_string(),
+            ColumnKind::To => self.to.to_string(),
+            ColumnKind::Correspondent if table.outgoing => self.to.to_string(),
+            ColumnKind::Correspondent => self.from.to_string(),
+            ColumnKind::Date => table.format_date(&self.date),
+            ColumnKind::Size => self.size.map(preview::format_size).unwrap_or_default(),
+            ColumnKind::Folder => self.folder.clone(),
+            ColumnKind::Account => self.account.clone(),
+            ColumnKind::Depth => self.depth.to_string(),
+        }
+    }
+}
 
-        row
+impl fmt::Display for Mailbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name.as_deref().unwrap_or(&self.addr))
     }
 }
 
@@ -89,11 +94,15 @@ impl Envelopes {
     pub fn try_from_lib(
         config: Arc<AccountConfig>,
         id_mapper: &IdMapper,
+        folder: &str,
         envelopes: email::envelope::Envelopes,
     ) -> Result<Envelopes> {
+        let depths = thread_depths(&envelopes);
+
         let envelopes = envelopes
             .iter()
-            .map(|envelope| {
+            .zip(depths)
+            .map(|(envelope, depth)| {
                 Ok(Envelope {
                     id: id_mapper.get_or_create_alias(&envelope.id)?,
                     flags: envelope.flags.clone().into(),
@@ -106,8 +115,12 @@ impl Envelopes {
                         name: envelope.to.name.clone(),
                         addr: envelope.to.addr.clone(),
                     },
-                    date: envelope.format_date(&config),
+                    date: envelope.date,
                     has_attachment: envelope.has_attachment,
+                    folder: folder.to_owned(),
+                    account: config.name.clone(),
+                    size: None,
+                    depth,
                 })
             })
             .collect::<Result<Vec<_>>>()?;
@@ -116,6 +129,44 @@ impl Envelopes {
     }
 }
 
+/// Computes the depth of the given envelopes in their thread, by
+/// following their In-Reply-To header. Only ancestors part of the
+/// given envelopes are counted.
+fn thread_depths(envelopes: &email::envelope::Envelopes) -> Vec<usize> {
+    let parents: HashMap<&str, &str> = envelopes
+        .iter()
+        .filter_map(|envelope| {
+            let parent = envelope.in_reply_to.as_deref()?;
+            Some((envelope.message_id.as_str(), parent))
+        })
+        .collect();
+
+    let listed: HashSet<&str> = envelopes
+        .iter()
+        .map(|envelope| envelope.message_id.as_str())
+        .collect();
+
+    envelopes
+        .iter()
+        .map(|envelope| {
+            let mut depth = 0;
+            let mut id = envelope.message_id.as_str();
+
+            // the depth is bounded to protect against cycles
+            while let Some(&parent) = parents.get(id).filter(|p| listed.contains(*p)) {
+                if depth == listed.len() {
+                    break;
+                }
+
+                depth += 1;
+                id = parent;
+            }
+
+            depth
+        })
+        .collect()
+}
+
 impl Deref for Envelopes {
     type Target = Vec<Envelope>;
 
@@ -124,71 +175,285 @@ impl Deref for Envelopes {
     }
 }
 
+impl DerefMut for Envelopes {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 impl Envelopes {
     pub fn new(envelopes: Vec<Envelope>) -> Self {
         Self(envelopes)
     }
 }
 
+/// The data shown by a column of the envelope listing table.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColumnKind {
+    Id,
+    Flags,
+    Subject,
+    From,
+    To,
+    /// Recipients in folders of outgoing messages like Sent and
+    /// Drafts, senders otherwise.
+    Correspondent,
+    Date,
+    Size,
+    Folder,
+    Account,
+    /// Depth of the envelope in its thread.
+    Depth,
+}
+
+impl ColumnKind {
+    fn header(&self, outgoing: bool) -> &'static str {
+        match self {
+            Self::Id => "ID",
+            Self::Flags => "FLAGS",
+            Self::Subject => "SUBJECT",
+            Self::From => "FROM",
+            Self::To => "TO",
+            Self::Correspondent if outgoing => "TO",
+            Self::Correspondent => "FROM",
+            Self::Date => "DATE",
+            Self::Size => "SIZE",
+            Self::Folder => "FOLDER",
+            Self::Account => "ACCOUNT",
+            Self::Depth => "DEPTH",
+        }
+    }
+
+    /// Whether the column can be truncated to fit the table width.
+    fn is_flexible(&self) -> bool {
+        matches!(
+            self,
+            Self::Subject | Self::From | Self::To | Self::Correspondent
+        )
+    }
+
+    fn default_alignment(&self) -> ColumnAlignment {
+        match self {
+            Self::Size | Self::Depth => ColumnAlignment::Right,
+            _ => ColumnAlignment::Left,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColumnAlignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl From<ColumnAlignment> for CellAlignment {
+    fn from(alignment: ColumnAlignment) -> Self {
+        match alignment {
+            ColumnAlignment::Left => CellAlignment::Left,
+            ColumnAlignment::Center => CellAlignment::Center,
+            ColumnAlignment::Right => CellAlignment::Right,
+        }
+    }
+}
+
+/// A column of the envelope listing table.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Column {
+    pub kind: ColumnKind,
+    /// Minimum width of the column.
+    pub min_width: Option<usize>,
+    /// Maximum width of the column, above which cells are truncated.
+    pub max_width: Option<usize>,
+    pub align: Option<ColumnAlignment>,
+}
+
+impl Column {
+    fn alignment(&self) -> ColumnAlignment {
+        self.align.unwrap_or(self.kind.default_alignment())
+    }
+}
+
+impl From<ColumnKind> for Column {
+    fn from(kind: ColumnKind) -> Self {
+        Self {
+            kind,
+            min_width: None,
+            max_width: None,
+            align: None,
+        }
+    }
+}
+
+/// A column given either by its kind only, or along with its
+/// options.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ColumnConfig {
+    Kind(ColumnKind),
+    Column(Column),
+}
+
+impl From<ColumnConfig> for Column {
+    fn from(config: ColumnConfig) -> Self {
+        match config {
+            ColumnConfig::Kind(kind) => kind.into(),
+            ColumnConfig::Column(column) => column,
+        }
+    }
+}
+
+/// How dates are shown in the envelope listing table.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DateTimeMode {
+    /// Formats dates with the datetime format.
+    #[default]
+    Format,
+    /// Shows the time of today's dates, the weekday of dates within
+    /// the last week, and the day of older ones.
+    Smart,
+}
+
+static DEFAULT_COLUMNS: [ColumnKind; 5] = [
+    ColumnKind::Id,
+    ColumnKind::Flags,
+    ColumnKind::Subject,
+    ColumnKind::Correspondent,
+    ColumnKind::Date,
+];
+
+/// Truncates the given text to the given display width, ending with
+/// an ellipsis.
+fn truncate(text: &str, width: Option<usize>) -> Cow<'_, str> {
+    match width {
+        Some(width) if text.width() > width => {
+            let mut truncated = String::new();
+            let mut truncated_width = 0;
+
+            for c in text.chars() {
+                let c_width = c.width().unwrap_or_default();
+
+                // keeps room for the ellipsis
+                if truncated_width + c_width >= width {
+                    break;
+                }
+
+                truncated.push(c);
+                truncated_width += c_width;
+            }
+
+            truncated.push('…');
+            Cow::Owned(truncated)
+        }
+        _ => Cow::Borrowed(text),
+    }
+}
+
+/// Computes the display width of a column from its header and its
+/// cells.
+fn column_width(header: &str, cells: impl IntoIterator<Item = impl AsRef<str>>) -> usize {
+    cells
+        .into_iter()
+        .map(|cell| cell.as_ref().width())
+        .fold(header.width(), usize::max)
+}
+
 pub struct EnvelopesTable {
     envelopes: Envelopes,
     width: Option<u16>,
+    theme: Theme,
+    columns: Vec<Column>,
+    outgoing: bool,
+    datetime_fmt: Option<String>,
+    datetime_local_tz: bool,
+    datetime_mode: DateTimeMode,
+    now: DateTime<Local>,
     config: ListEnvelopesTableConfig,
 }
 
 impl EnvelopesTable {
+    /// Fits the table in the given width.
+    ///
+    /// Subjects and addresses are truncated when the table does not
+    /// fit, addresses taking at most a third of the remaining space.
     pub fn with_some_width(mut self, width: Option<u16>) -> Self {
         self.width = width;
         self
     }
 
-    pub fn with_some_preset(mut self, preset: Option<String>) -> Self {
-        self.config.preset = preset;
+    /// Colours the table with the given theme. Defaults to the dark
+    /// theme.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
         self
     }
 
-    pub fn with_some_unseen_char(mut self, char: Option<char>) -> Self {
-        self.config.unseen_char = char;
+    /// Chooses and orders the columns of the table. Defaults to ID,
+    /// flags, subject, correspondent and date, which are also used
+    /// when no column is given.
+    pub fn with_some_columns(mut self, columns: Option<Vec<Column>>) -> Self {
+        if let Some(columns) = columns.filter(|columns| !columns.is_empty()) {
+            self.columns = columns;
+        }
         self
     }
 
-    pub fn with_some_replied_char(mut self, char: Option<char>) -> Self {
-        self.config.replied_char = char;
+    /// Shows recipients instead of senders in correspondent columns,
+    /// for folders of outgoing messages.
+    pub fn with_outgoing(mut self, outgoing: bool) -> Self {
+        self.outgoing = outgoing;
         self
     }
 
-    pub fn with_some_flagged_char(mut self, char: Option<char>) -> Self {
-        self.config.flagged_char = char;
+    pub fn with_some_datetime_fmt(mut self, fmt: Option<String>) -> Self {
+        self.datetime_fmt = fmt;
         self
     }
 
-    pub fn with_some_attachment_char(mut self, char: Option<char>) -> Self {
-        self.config.attachment_char = char;
+    pub fn with_datetime_local_tz(mut self, local_tz: bool) -> Self {
+        self.datetime_local_tz = local_tz;
         self
     }
 
-    pub fn with_some_id_color(mut self, color: Option<Color>) -> Self {
-        self.config.id_color = color;
+    pub fn with_some_datetime_mode(mut self, mode: Option<DateTimeMode>) -> Self {
+        self.datetime_mode = mode.unwrap_or_default();
         self
     }
 
-    pub fn with_some_flags_color(mut self, color: Option<Color>) -> Self {
-        self.config.flags_color = color;
+    /// Sets the date smart dates are relative to. Defaults to the
+    /// creation date of the table.
+    pub fn with_now(mut self, now: DateTime<Local>) -> Self {
+        self.now = now;
         self
     }
 
-    pub fn with_some_subject_color(mut self, color: Option<Color>) -> Self {
-        self.config.subject_color = color;
+    pub fn with_some_preset(mut self, preset: Option<String>) -> Self {
+        self.config.preset = preset;
         self
     }
 
-    pub fn with_some_sender_color(mut self, color: Option<Color>) -> Self {
-        self.config.sender_color = color;
+    pub fn with_some_unseen_char(mut self, char: Option<char>) -> Self {
+        self.config.unseen_char = char;
         self
     }
 
-    pub fn with_some_date_color(mut self, color: Option<Color>) -> Self {
-        self.config.date_color = color;
+    pub fn with_some_replied_char(mut self, char: Option<char>) -> Self {
+        self.config.replied_char = char;
+        self
+    }
+
+    pub fn with_some_flagged_char(mut self, char: Option<char>) -> Self {
+        self.config.flagged_char = char;
+        self
+    }
+
+    pub fn with_some_attachment_char(mut self, char: Option<char>) -> Self {
+        self.config.attachment_char = char;
         self
     }
 }
@@ -198,31 +463,210 @@ impl From<Envelopes> for EnvelopesTable {
         Self {
             envelopes,
             width: None,
+            theme: Theme::default(),
+            columns: DEFAULT_COLUMNS.map(Column::from).to_vec(),
+            outgoing: false,
+            datetime_fmt: None,
+            datetime_local_tz: false,
+            datetime_mode: DateTimeMode::default(),
+            now: Local::now(),
             config: Default::default(),
         }
     }
 }
 
+impl EnvelopesTable {
+    fn column_color(&self, kind: ColumnKind) -> Option<Color> {
+        match kind {
+            ColumnKind::Id => self.theme.envelope_id,
+            ColumnKind::Flags => self.theme.envelope_flags,
+            ColumnKind::Subject => self.theme.envelope_subject,
+            ColumnKind::From | ColumnKind::To | ColumnKind::Correspondent => {
+                self.theme.envelope_sender
+            }
+            ColumnKind::Date => self.theme.envelope_date,
+            _ => None,
+        }
+    }
+
+    fn format_date(&self, date: &DateTime<FixedOffset>) -> String {
+        let date = if self.datetime_local_tz {
+            DateTime::<FixedOffset>::from(date.with_timezone(&Local))
+        } else {
+            *date
+        };
+
+        let fmt = match self.datetime_mode {
+            DateTimeMode::Format => self.datetime_fmt.as_deref().unwrap_or("%F %R%:z"),
+            DateTimeMode::Smart => {
+                let today = self.now.with_timezone(date.offset()).date_naive();
+
+                match (today - date.date_naive()).num_days() {
+                    0 => "%R",
+                    1..=6 => "%a",
+                    _ => "%F",
+                }
+            }
+        };
+
+        date.format(fmt).to_string()
+    }
+
+    /// Computes the maximum widths of the flexible columns for the
+    /// table to fit in its width.
+    fn fit_widths(&self, headers: &[&str], cells: &[Vec<String>]) -> Vec<Option<usize>> {
+        let mut fits = vec![None; self.columns.len()];
+
+        let Some(width) = self.width.map(usize::from) else {
+            return fits;
+        };
+
+        let mins: Vec<usize> = self
+            .columns
+            .iter()
+            .zip(headers)
+            .map(|(column, header)| column.min_width.unwrap_or_default().max(header.width()))
+            .collect();
+
+        let naturals: Vec<usize> = (0..self.columns.len())
+            .map(|i| column_width(headers[i], cells.iter().map(|row| &row[i])).max(mins[i]))
+            .collect();
+
+        let (flexible, fixed): (Vec<usize>, Vec<usize>) =
+            (0..self.columns.len()).partition(|i| self.columns[*i].kind.is_flexible());
+
+        // cells are padded with one space on each side, borders and
+        // vertical lines are only drawn if the preset defines them
+        let preset: Vec<char> = self.config.preset().chars().collect();
+        let drawn = |i: usize| preset.get(i).is_some_and(|c| *c != ' ');
+        let borders = usize::from(drawn(0)) + usize::from(drawn(1));
+        let lines = if drawn(8) { self.columns.len() - 1 } else { 0 };
+        let overhead = self.columns.len() * 2 + borders + lines;
+        let fixed: usize = fixed.iter().map(|i| naturals[*i]).sum();
+
+        let Some(available) = width.checked_sub(overhead + fixed) else {
+            return fits;
+        };
+
+        let min: usize = flexible.iter().map(|i| mins[*i]).sum();
+        let natural: usize = flexible.iter().map(|i| naturals[*i]).sum();
+
+        if available < min || natural <= available {
+            return fits;
+        }
+
+        let (subjects, addresses): (Vec<usize>, Vec<usize>) = flexible
+            .into_iter()
+            .partition(|i| self.columns[*i].kind == ColumnKind::Subject);
+
+        let mut widths = vec![0; self.columns.len()];
+
+        // addresses take at most a third of the available space,
+        // subjects the rest
+        let share = available / 3 / addresses.len().max(1);
+        for &i in &addresses {
+            widths[i] = naturals[i].min(share.max(mins[i]));
+        }
+
+        let taken: usize = widths.iter().sum();
+        let share = available.saturating_sub(taken) / subjects.len().max(1);
+        for &i in &subjects {
+            widths[i] = naturals[i].min(share.max(mins[i]));
+        }
+
+        // space left by short subjects goes back to addresses
+        let mut left = available.saturating_sub(widths.iter().sum());
+        for &i in addresses.iter().chain(&subjects) {
+            let extra = left.min(naturals[i] - widths[i]);
+            widths[i] += extra;
+            left -= extra;
+            fits[i] = Some(widths[i]);
+        }
+
+        fits
+    }
+}
+
 impl fmt::Display for EnvelopesTable {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut table = Table::new();
 
+        let headers: Vec<&str> = self
+            .columns
+            .iter()
+            .map(|column| column.kind.header(self.outgoing))
+            .collect();
+
+        let cells: Vec<Vec<String>> = self
+            .envelopes
+            .iter()
+            .map(|env| {
+                self.columns
+                    .iter()
+                    .map(|column| {
+                        let cell = env.cell(column.kind, self);
+                        truncate(&cell, column.max_width).into_owned()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let fits = self.fit_widths(&headers, &cells);
+
+        let rows = self.envelopes.iter().zip(&cells).map(|(env, cells)| {
+            let mut attributes = vec![];
+
+            if env.is_unseen() {
+                attributes.push(Attribute::Bold)
+            }
+
+            let mut row = Row::new();
+            row.max_height(1);
+
+            for ((column, cell), fit) in self.columns.iter().zip(cells).zip(&fits) {
+                let mut cell = Cell::new(truncate(cell, *fit)).add_attributes(attributes.clone());
+
+                if let Some(color) = self.column_color(column.kind) {
+                    cell = cell.fg(table_color(color));
+                }
+
+                row.add_cell(cell);
+            }
+
+            row
+        });
+
         table
             .load_preset(self.config.preset())
             .set_content_arrangement(ContentArrangement::DynamicFullWidth)
-            .set_header(Row::from([
-                Cell::new("ID"),
-                Cell::new("FLAGS"),
-                Cell::new("SUBJECT"),
-                Cell::new("FROM"),
-                Cell::new("DATE"),
-            ]))
-            .add_rows(self.envelopes.iter().map(|env| env.to_row(&self.config)));
+            .set_header(Row::from(headers.iter().map(Cell::new)))
+            .add_rows(rows);
+
+        for (i, column) in self.columns.iter().enumerate() {
+            let Some(table_column) = table.column_mut(i) else {
+                continue;
+            };
+
+            table_column.set_cell_alignment(column.alignment().into());
+
+            // constraints include the padding of the cells
+            if let Some(min_width) = column.min_width {
+                let min_width = min_width + usize::from(table_column.get_padding_width());
+                let min_width = Width::Fixed(min_width.try_into().unwrap_or(u16::MAX));
+                table_column.set_constraint(ColumnConstraint::LowerBoundary(min_width));
+            }
+        }
 
         if let Some(width) = self.width {
             table.set_width(width);
         }
 
+        if self.theme.styling {
+            table.enforce_styling();
+        } else {
+            table.force_no_tty();
+        }
+
         writeln!(f)?;
         write!(f, "{table}")?;
         writeln!(f)?;
@@ -239,12 +683,6 @@ pub struct ListEnvelopesTableConfig {
     pub replied_char: Option<char>,
     pub flagged_char: Option<char>,
     pub attachment_char: Option<char>,
-
-    pub id_color: Option<Color>,
-    pub flags_color: Option<Color>,
-    pub subject_color: Option<Color>,
-    pub sender_color: Option<Color>,
-    pub date_color: Option<Color>,
 }
 
 impl ListEnvelopesTableConfig {
@@ -283,30 +721,10 @@ impl ListEnvelopesTableConfig {
             ' '
         }
     }
-
-    pub fn id_color(&self) -> comfy_table::Color {
-        map_color(self.id_color.unwrap_or(Color::Red))
-    }
-
-    pub fn flags_color(&self) -> comfy_table::Color {
-        map_color(self.flags_color.unwrap_or(Color::Reset))
-    }
-
-    pub fn subject_color(&self) -> comfy_table::Color {
-        map_color(self.subject_color.unwrap_or(Color::Green))
-    }
-
-    pub fn sender_color(&self) -> comfy_table::Color {
-        map_color(self.sender_color.unwrap_or(Color::Blue))
-    }
-
-    pub fn date_color(&self) -> comfy_table::Color {
-        map_color(self.date_color.unwrap_or(Color::DarkYellow))
-    }
 }
 
 /// Represents the flag variants.
-#[derive(Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd, Serialize)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum Flag {
     Seen,
     Answered,
@@ -330,6 +748,20 @@ impl From<&email::flag::Flag> for Flag {
     }
 }
 
+impl From<&Flag> for email::flag::Flag {
+    fn from(flag: &Flag) -> Self {
+        use email::flag::Flag::*;
+        match flag {
+            Flag::Seen => Seen,
+            Flag::Answered => Answered,
+            Flag::Flagged => Flagged,
+            Flag::Deleted => Deleted,
+            Flag::Draft => Draft,
+            Flag::Custom(flag) => Custom(flag.clone()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub struct Flags(pub HashSet<Flag>);
 
@@ -346,3 +778,349 @@ impl From<email::flag::Flags> for Flags {
         Flags(flags.iter().map(Flag::from).collect())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, FixedOffset, Local};
+    use comfy_table::presets;
+    use crossterm::style::Color;
+
+    use super::{
+        truncate, Column, ColumnAlignment, ColumnConfig, ColumnKind, DateTimeMode, Envelope,
+        Envelopes, EnvelopesTable, Flag, Flags, Mailbox,
+    };
+    use crate::theme::{Theme, ThemeKind};
+
+    fn envelope(id: &str, flags: &[Flag], subject: &str, from: Mailbox, date: &str) -> Envelope {
+        Envelope {
+            id: id.to_owned(),
+            flags: Flags(flags.iter().cloned().collect()),
+            subject: subject.to_owned(),
+            from,
+            date: self::date(date),
+            ..Default::default()
+        }
+    }
+
+    fn date(date: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_str(&format!("{date} +0000"), "%F %R %z").unwrap()
+    }
+
+    fn mailbox(name: Option<&str>, addr: &str) -> Mailbox {
+        Mailbox {
+            name: name.map(ToOwned::to_owned),
+            addr: addr.to_owned(),
+        }
+    }
+
+    fn envelopes() -> Envelopes {
+        let mut reply = envelope(
+            "2",
+            &[Flag::Seen, Flag::Flagged, Flag::Answered],
+            "Re: Meeting",
+            mailbox(None, "alice@localhost"),
+            "2024-01-02 09:30",
+        );
+        reply.has_attachment = true;
+
+        Envelopes::new(vec![
+            envelope(
+                "1",
+                &[],
+                "Hello",
+                mailbox(Some("Bob"), "bob@localhost"),
+                "2024-01-01 10:00",
+            ),
+            reply,
+        ])
+    }
+
+    fn table(envelopes: Envelopes) -> EnvelopesTable {
+        EnvelopesTable::from(envelopes)
+            .with_theme(Theme::new(ThemeKind::Dark).with_styling(false))
+            .with_some_datetime_fmt(Some(String::from("%F %R")))
+    }
+
+    #[test]
+    fn default_preset() {
+        let table = table(envelopes()).to_string();
+
+        let expected = "
+| ID | FLAGS | SUBJECT     | FROM            | DATE             |
+|----|-------|-------------|-----------------|------------------|
+| 1  |  *    | Hello       | Bob             | 2024-01-01 10:00 |
+| 2  | ! @R  | Re: Meeting | alice@localhost | 2024-01-02 09:30 |
+";
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn custom_preset() {
+        let table = table(envelopes())
+            .with_some_preset(Some(presets::ASCII_FULL.to_owned()))
+            .to_string();
+
+        let expected = "
++----+-------+-------------+-----------------+------------------+
+| ID | FLAGS | SUBJECT     | FROM            | DATE             |
++===============================================================+
+| 1  |  *    | Hello       | Bob             | 2024-01-01 10:00 |
+|----+-------+-------------+-----------------+------------------|
+| 2  | ! @R  | Re: Meeting | alice@localhost | 2024-01-02 09:30 |
++----+-------+-------------+-----------------+------------------+
+";
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn custom_flag_chars() {
+        let table = table(envelopes())
+            .with_some_unseen_char(Some('N'))
+            .with_some_flagged_char(Some('F'))
+            .with_some_attachment_char(Some('A'))
+            .with_some_replied_char(Some('r'))
+            .to_string();
+
+        let expected = "
+| ID | FLAGS | SUBJECT     | FROM            | DATE             |
+|----|-------|-------------|-----------------|------------------|
+| 1  |  N    | Hello       | Bob             | 2024-01-01 10:00 |
+| 2  | F Ar  | Re: Meeting | alice@localhost | 2024-01-02 09:30 |
+";
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn unicode_subject() {
+        let envelopes = Envelopes::new(vec![envelope(
+            "3",
+            &[Flag::Seen],
+            "日本語のメール",
+            mailbox(Some("山田太郎"), "taro@localhost"),
+            "2024-01-03 08:00",
+        )]);
+
+        let table = table(envelopes).to_string();
+
+        let expected = "
+| ID | FLAGS | SUBJECT        | FROM     | DATE             |
+|----|-------|----------------|----------|------------------|
+| 3  |       | 日本語のメール | 山田太郎 | 2024-01-03 08:00 |
+";
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn narrow_width() {
+        let mut envelopes = envelopes().to_vec();
+        envelopes[1].subject = String::from("Re: Meeting about the quarterly budget");
+
+        let table = table(Envelopes::new(envelopes))
+            .with_some_width(Some(60))
+            .to_string();
+
+        let expected = "
+| ID | FLAGS | SUBJECT        | FROM    | DATE             |
+|----|-------|----------------|---------|------------------|
+| 1  |  *    | Hello          | Bob     | 2024-01-01 10:00 |
+| 2  | ! @R  | Re: Meeting a… | alice@… | 2024-01-02 09:30 |
+";
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn wide_width() {
+        let table = table(envelopes()).with_some_width(Some(200)).to_string();
+
+        assert!(table.contains("| Re: Meeting "));
+        assert!(table.contains("| alice@localhost "));
+        assert!(!table.contains('…'));
+    }
+
+    #[test]
+    fn colors() {
+        let theme = Theme::new(ThemeKind::Dark).with_styling(true);
+        let output = EnvelopesTable::from(envelopes())
+            .with_theme(theme.clone())
+            .to_string();
+
+        // dark theme: red ids, green subjects, blue senders and dark
+        // yellow dates
+        assert!(output.contains("\u{1b}[38;5;9m"));
+        assert!(output.contains("\u{1b}[38;5;10m"));
+        assert!(output.contains("\u{1b}[38;5;12m"));
+        assert!(output.contains("\u{1b}[38;5;3m"));
+
+        let output = EnvelopesTable::from(envelopes())
+            .with_theme(Theme {
+                envelope_id: Some(Color::Magenta),
+                ..theme
+            })
+            .to_string();
+
+        assert!(output.contains("\u{1b}[38;5;13m"));
+        assert!(!output.contains("\u{1b}[38;5;9m"));
+
+        // monochrome theme: bold unseen envelopes only
+        let output = EnvelopesTable::from(envelopes())
+            .with_theme(Theme::new(ThemeKind::Monochrome).with_styling(true))
+            .to_string();
+
+        assert!(output.contains("\u{1b}[1m"));
+        assert!(!output.contains("\u{1b}[38;5;"));
+
+        let output = table(envelopes()).to_string();
+        assert!(!output.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn custom_columns() {
+        let mut envelopes = envelopes().to_vec();
+        envelopes[0].to = mailbox(None, "alice@localhost");
+        envelopes[0].size = Some(512);
+        envelopes[1].to = mailbox(Some("Bob"), "bob@localhost");
+        envelopes[1].size = Some(2048);
+        envelopes[1].depth = 1;
+
+        let columns = [
+            ColumnKind::Id,
+            ColumnKind::Subject,
+            ColumnKind::To,
+            ColumnKind::Size,
+            ColumnKind::Depth,
+        ];
+
+        let table = table(Envelopes::new(envelopes))
+            .with_some_columns(Some(columns.map(Column::from).to_vec()))
+            .to_string();
+
+        let expected = "
+| ID | SUBJECT     | TO              |    SIZE | DEPTH |
+|----|-------------|-----------------|---------|-------|
+| 1  | Hello       | alice@localhost |   512 B |     0 |
+| 2  | Re: Meeting | Bob             | 2.0 KiB |     1 |
+";
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn empty_columns() {
+        let expected = table(envelopes()).to_string();
+
+        let table = table(envelopes())
+            .with_some_columns(Some(Vec::new()))
+            .with_some_width(Some(80))
+            .to_string();
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn outgoing_correspondent() {
+        let mut envelopes = envelopes().to_vec();
+        envelopes[0].to = mailbox(None, "alice@localhost");
+        envelopes[1].to = mailbox(Some("Bob"), "bob@localhost");
+
+        let table = table(Envelopes::new(envelopes))
+            .with_outgoing(true)
+            .to_string();
+
+        let expected = "
+| ID | FLAGS | SUBJECT     | TO              | DATE             |
+|----|-------|-------------|-----------------|------------------|
+| 1  |  *    | Hello       | alice@localhost | 2024-01-01 10:00 |
+| 2  | ! @R  | Re: Meeting | Bob             | 2024-01-02 09:30 |
+";
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn column_widths_and_alignment() {
+        let columns = vec![
+            Column {
+                min_width: Some(4),
+                align: Some(ColumnAlignment::Right),
+                ..ColumnKind::Id.into()
+            },
+            Column {
+                max_width: Some(8),
+                ..ColumnKind::Subject.into()
+            },
+            Column {
+                align: Some(ColumnAlignment::Center),
+                ..ColumnKind::From.into()
+            },
+        ];
+
+        let table = table(envelopes())
+            .with_some_columns(Some(columns))
+            .to_string();
+
+        let expected = "
+|   ID | SUBJECT  |      FROM       |
+|------|----------|-----------------|
+|    1 | Hello    |       Bob       |
+|    2 | Re: Mee… | alice@localhost |
+";
+
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn parse_columns() {
+        let config: toml::Value =
+            toml::from_str(r#"columns = ["id", { kind = "to", max-width = 20, align = "right" }]"#)
+                .unwrap();
+
+        let columns: Vec<ColumnConfig> = config["columns"].clone().try_into().unwrap();
+        let columns: Vec<Column> = columns.into_iter().map(Into::into).collect();
+
+        assert_eq!(
+            columns,
+            [
+                ColumnKind::Id.into(),
+                Column {
+                    max_width: Some(20),
+                    align: Some(ColumnAlignment::Right),
+                    ..ColumnKind::To.into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn default_date_format() {
+        let table = EnvelopesTable::from(Envelopes::default());
+        let date = table.format_date(&date("2024-01-01 10:00"));
+        assert_eq!(date, "2024-01-01 10:00+00:00");
+    }
+
+    #[test]
+    fn smart_dates() {
+        let table = table(Envelopes::default())
+            .with_some_datetime_mode(Some(DateTimeMode::Smart))
+            .with_now(date("2024-01-10 12:00").with_timezone(&Local));
+
+        assert_eq!(table.format_date(&date("2024-01-10 08:15")), "08:15");
+        assert_eq!(table.format_date(&date("2024-01-08 09:00")), "Mon");
+        assert_eq!(table.format_date(&date("2024-01-04 18:30")), "Thu");
+        assert_eq!(table.format_date(&date("2024-01-03 18:30")), "2024-01-03");
+        assert_eq!(table.format_date(&date("2023-12-25 10:00")), "2023-12-25");
+    }
+
+    #[test]
+    fn truncate_text() {
+        assert_eq!(truncate("Hello", None), "Hello");
+        assert_eq!(truncate("Hello", Some(5)), "Hello");
+        assert_eq!(truncate("Hello world", Some(6)), "Hello…");
+        assert_eq!(truncate("日本語のメールです", Some(9)), "日本語の…");
+        assert_eq!(truncate("日本語のメールです", Some(10)), "日本語の…");
+    }
+}
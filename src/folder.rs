@@ -0,0 +1,68 @@
+//! Folder module.
+//!
+//! Folders of the backend are listed with the `folders` command, in a
+//! table coloured with the theme of the session.
+
+use std::fmt;
+
+use comfy_table::{presets, Cell, ContentArrangement, Row, Table};
+
+use crate::theme::{table_color, Theme};
+
+pub struct FoldersTable {
+    folders: email::folder::Folders,
+    theme: Theme,
+}
+
+impl FoldersTable {
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+impl From<email::folder::Folders> for FoldersTable {
+    fn from(folders: email::folder::Folders) -> Self {
+        Self {
+            folders,
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl fmt::Display for FoldersTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+
+        let cell = |text: &str, color| {
+            let cell = Cell::new(text);
+            match color {
+                Some(color) => cell.fg(table_color(color)),
+                None => cell,
+            }
+        };
+
+        table
+            .load_preset(presets::ASCII_MARKDOWN)
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_header(Row::from([Cell::new("NAME"), Cell::new("DESC")]))
+            .add_rows(self.folders.iter().map(|folder| {
+                let mut row = Row::new();
+                row.max_height(1);
+                row.add_cell(cell(&folder.name, self.theme.folder_name))
+                    .add_cell(cell(&folder.desc, self.theme.folder_desc));
+                row
+            }));
+
+        if self.theme.styling {
+            table.enforce_styling();
+        } else {
+            table.force_no_tty();
+        }
+
+        writeln!(f)?;
+        write!(f, "{table}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
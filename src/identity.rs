@@ -0,0 +1,171 @@
+//! Sender identities module.
+//!
+//! An account can declare alternative identities, each one with its
+//! own address, display name and signature. Identities only affect
+//! composed messages: the account backends stay the same.
+
+use std::{fmt, sync::Arc};
+
+use color_eyre::Result;
+use comfy_table::{presets, Cell, ContentArrangement, Row, Table};
+use email::{account::config::AccountConfig, message::Message};
+
+use crate::account::config::TomlAccountConfig;
+
+/// The name of the identity made of the account options.
+pub static DEFAULT_IDENTITY: &str = "default";
+
+/// Headers of the original message checked to find out which
+/// identity it was sent to.
+static RECIPIENT_HEADERS: [&str; 4] = ["To", "Cc", "Delivered-To", "X-Original-To"];
+
+#[derive(Clone, Debug)]
+pub struct Identity {
+    pub name: String,
+    pub config: Arc<AccountConfig>,
+}
+
+impl Identity {
+    fn matches(&self, addr: &str) -> bool {
+        let addr = addr.trim().trim_start_matches('<').trim_end_matches('>');
+        self.config.email.eq_ignore_ascii_case(addr)
+    }
+}
+
+/// The identities of an account, including the default one.
+#[derive(Clone, Debug)]
+pub struct Identities {
+    identities: Vec<Identity>,
+    current: usize,
+}
+
+impl Identities {
+    pub fn new(
+        account_config: Arc<AccountConfig>,
+        toml_account_config: &TomlAccountConfig,
+    ) -> Self {
+        let mut identities = vec![Identity {
+            name: DEFAULT_IDENTITY.to_owned(),
+            config: account_config.clone(),
+        }];
+
+        let mut aliases = Vec::from_iter(toml_account_config.identities.iter().flatten());
+        aliases.sort_by_key(|(name, _)| *name);
+
+        for (name, alias) in aliases {
+            let config = AccountConfig {
+                email: alias.email.clone(),
+                display_name: alias
+                    .display_name
+                    .clone()
+                    .or(account_config.display_name.clone()),
+                signature: alias.signature.clone().or(account_config.signature.clone()),
+                signature_delim: alias
+                    .signature_delim
+                    .clone()
+                    .or(account_config.signature_delim.clone()),
+                ..(*account_config).clone()
+            };
+
+            identities.push(Identity {
+                name: name.clone(),
+                config: Arc::new(config),
+            });
+        }
+
+        Self {
+            identities,
+            current: 0,
+        }
+    }
+
+    /// Returns the identity selected for the session.
+    pub fn current(&self) -> &Identity {
+        &self.identities[self.current]
+    }
+
+    /// Selects the identity matching the given name or address for
+    /// the session.
+    ///
+    /// Returns `None` if no identity matches, in which case the
+    /// selected identity is left untouched.
+    pub fn select(&mut self, name: &str) -> Option<&Identity> {
+        self.current = self
+            .identities
+            .iter()
+            .position(|identity| identity.name == name || identity.matches(name))?;
+
+        Some(self.current())
+    }
+
+    /// Finds the identity the given message was sent to, in order to
+    /// reply with it. Falls back to the identity of the session.
+    pub fn find_for_reply(&self, message: &Message) -> Result<&Identity> {
+        let message = message.parsed()?;
+
+        for header in RECIPIENT_HEADERS {
+            let addrs = message
+                .header(header)
+                .and_then(|header| header.as_address())
+                .into_iter()
+                .flat_map(|addrs| addrs.iter())
+                .filter_map(|addr| addr.address());
+
+            for addr in addrs {
+                if let Some(identity) = self.identities.iter().find(|i| i.matches(addr)) {
+                    return Ok(identity);
+                }
+            }
+
+            // Delivered-To like headers contain raw addresses
+            let raw = message.header_raw(header).unwrap_or_default();
+
+            if let Some(identity) = self.identities.iter().find(|i| i.matches(raw)) {
+                return Ok(identity);
+            }
+        }
+
+        Ok(self.current())
+    }
+}
+
+pub struct IdentitiesTable<'a>(&'a Identities);
+
+impl<'a> From<&'a Identities> for IdentitiesTable<'a> {
+    fn from(identities: &'a Identities) -> Self {
+        Self(identities)
+    }
+}
+
+impl fmt::Display for IdentitiesTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+        let current = &self.0.current().name;
+
+        table
+            .load_preset(presets::ASCII_MARKDOWN)
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_header(Row::from([
+                Cell::new(""),
+                Cell::new("NAME"),
+                Cell::new("ADDRESS"),
+                Cell::new("DISPLAY NAME"),
+            ]))
+            .add_rows(self.0.identities.iter().map(|identity| {
+                let mut row = Row::new();
+                row.max_height(1);
+                row.add_cell(Cell::new(if &identity.name == current { "*" } else { "" }))
+                    .add_cell(Cell::new(&identity.name))
+                    .add_cell(Cell::new(&identity.config.email))
+                    .add_cell(Cell::new(
+                        identity.config.display_name.as_deref().unwrap_or_default(),
+                    ));
+                row
+            }));
+
+        writeln!(f)?;
+        write!(f, "{table}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
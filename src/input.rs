@@ -0,0 +1,198 @@
+//! Input module.
+//!
+//! The REPL reads command lines and prompt answers through the
+//! [`Input`] trait, either from the terminal or from a script, which
+//! allows sessions to be driven by tests.
+
+use std::{
+    collections::VecDeque,
+    io::Write,
+    ops::{Deref, DerefMut},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use pimalaya_tui::prompt;
+use reedline::{
+    ColumnarMenu, DefaultCompleter, MenuBuilder, Prompt, Reedline, ReedlineMenu, Signal,
+};
+
+use crate::{config::KeybindsStyle, edit_mode, session::COMMANDS};
+
+pub trait Input {
+    /// Reads the next command line, or returns `None` once the input
+    /// is over.
+    fn read_line(&mut self, prompt: &dyn Prompt) -> Result<Option<String>>;
+
+    fn text(&mut self, message: &str, default: Option<&str>) -> Result<String>;
+
+    fn bool(&mut self, message: &str, default: bool) -> Result<bool>;
+
+    fn usize(&mut self, message: &str, default: Option<usize>) -> Result<usize>;
+
+    /// Selects one of the given items, and returns its index.
+    fn select(&mut self, message: &str, items: &[String]) -> Result<usize>;
+}
+
+/// The input and outputs of a session, lent to the interactive flows
+/// running outside of the REPL loop, like the editor one.
+pub struct Io<'a> {
+    pub input: &'a mut dyn Input,
+    pub out: &'a mut dyn Write,
+    pub err: &'a mut dyn Write,
+}
+
+/// Selects one of the given items using the given input.
+pub fn item<T: ToString>(
+    input: &mut dyn Input,
+    message: &str,
+    items: impl IntoIterator<Item = T>,
+) -> Result<T> {
+    let mut items: Vec<T> = items.into_iter().collect();
+    let labels: Vec<String> = items.iter().map(ToString::to_string).collect();
+    let index = input.select(message, &labels)?;
+    Ok(items.swap_remove(index))
+}
+
+struct UnselectedMode(Reedline);
+
+impl UnselectedMode {
+    pub fn new(keybinds: KeybindsStyle, styling: bool) -> impl DerefMut<Target = Reedline> {
+        let completer = Box::new(DefaultCompleter::new_with_wordlen(
+            COMMANDS.iter().map(ToString::to_string).collect(),
+            0,
+        ));
+
+        let completion = Box::new(ColumnarMenu::default().with_name("completion"));
+
+        let reedline = Reedline::create()
+            .with_completer(completer)
+            .with_menu(ReedlineMenu::EngineCompleter(completion))
+            .with_edit_mode(edit_mode(&keybinds))
+            .with_ansi_colors(styling);
+
+        Self(reedline)
+    }
+}
+
+impl Deref for UnselectedMode {
+    type Target = Reedline;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for UnselectedMode {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Input reading command lines with reedline, and prompt answers
+/// with interactive terminal prompts.
+pub struct TerminalInput {
+    mode: Box<dyn DerefMut<Target = Reedline>>,
+}
+
+impl TerminalInput {
+    /// Creates a terminal input with the given keybinds, colouring
+    /// the prompt only if styling is enabled.
+    pub fn new(keybinds: KeybindsStyle, styling: bool) -> Self {
+        Self {
+            mode: Box::new(UnselectedMode::new(keybinds, styling)),
+        }
+    }
+}
+
+impl Input for TerminalInput {
+    fn read_line(&mut self, prompt: &dyn Prompt) -> Result<Option<String>> {
+        match self.mode.read_line(prompt)? {
+            Signal::Success(line) => Ok(Some(line)),
+            Signal::CtrlD | Signal::CtrlC => Ok(None),
+        }
+    }
+
+    fn text(&mut self, message: &str, default: Option<&str>) -> Result<String> {
+        Ok(prompt::text(message, default)?)
+    }
+
+    fn bool(&mut self, message: &str, default: bool) -> Result<bool> {
+        Ok(prompt::bool(message, default)?)
+    }
+
+    fn usize(&mut self, message: &str, default: Option<usize>) -> Result<usize> {
+        Ok(prompt::usize(message, default)?)
+    }
+
+    fn select(&mut self, message: &str, items: &[String]) -> Result<usize> {
+        let item = prompt::item(message, items, None)?;
+        let index = items.iter().position(|i| i == item).unwrap_or_default();
+        Ok(index)
+    }
+}
+
+/// Input reading command lines and prompt answers from a script, one
+/// per line.
+///
+/// Prompts panic once the script is over, since interactive flows
+/// like the post-edit one keep prompting until they get a valid
+/// answer.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedInput {
+    lines: VecDeque<String>,
+}
+
+impl ScriptedInput {
+    pub fn new(lines: impl IntoIterator<Item = impl ToString>) -> Self {
+        Self {
+            lines: lines.into_iter().map(|line| line.to_string()).collect(),
+        }
+    }
+
+    fn answer(&mut self, message: &str) -> String {
+        match self.lines.pop_front() {
+            Some(answer) => answer,
+            None => panic!("script is over, cannot answer prompt {message:?}"),
+        }
+    }
+}
+
+impl Input for ScriptedInput {
+    fn read_line(&mut self, _prompt: &dyn Prompt) -> Result<Option<String>> {
+        Ok(self.lines.pop_front())
+    }
+
+    fn text(&mut self, message: &str, default: Option<&str>) -> Result<String> {
+        match self.answer(message) {
+            answer if answer.is_empty() => Ok(default.unwrap_or_default().to_owned()),
+            answer => Ok(answer),
+        }
+    }
+
+    fn bool(&mut self, message: &str, default: bool) -> Result<bool> {
+        match self.answer(message).trim().to_lowercase().as_str() {
+            "" => Ok(default),
+            "y" | "yes" => Ok(true),
+            "n" | "no" => Ok(false),
+            answer => Err(eyre!("invalid answer {answer:?} to prompt {message:?}")),
+        }
+    }
+
+    fn usize(&mut self, message: &str, default: Option<usize>) -> Result<usize> {
+        match self.answer(message).trim() {
+            "" => default.ok_or_else(|| eyre!("missing answer to prompt {message:?}")),
+            answer => answer
+                .parse()
+                .map_err(|_| eyre!("invalid answer {answer:?} to prompt {message:?}")),
+        }
+    }
+
+    fn select(&mut self, message: &str, items: &[String]) -> Result<usize> {
+        let answer = self.answer(message);
+
+        items
+            .iter()
+            .position(|item| item == answer.trim())
+            .ok_or_else(|| eyre!("invalid answer {answer:?} to prompt {message:?}"))
+    }
+}
@@ -0,0 +1,59 @@
+pub mod account;
+pub mod backend;
+pub mod cli;
+pub mod compose;
+pub mod config;
+pub mod contact;
+pub mod draft;
+pub mod editor;
+pub mod envelope;
+pub mod folder;
+pub mod id_mapper;
+pub mod identity;
+pub mod input;
+#[cfg(feature = "memory")]
+pub mod memory;
+pub mod message;
+#[cfg(feature = "sync")]
+pub mod offline;
+pub mod outbox;
+pub mod pager;
+pub mod pgp;
+pub mod preview;
+pub mod queue;
+pub mod reader;
+pub mod session;
+#[cfg(feature = "sync")]
+pub mod sync;
+pub mod templates;
+pub mod theme;
+pub mod validation;
+
+use reedline::{
+    default_emacs_keybindings, default_vi_insert_keybindings, default_vi_normal_keybindings,
+    EditMode, Emacs, KeyCode, KeyModifiers, ReedlineEvent, Vi,
+};
+
+use crate::config::KeybindsStyle;
+
+/// Builds the reedline edit mode matching the given keybinds style,
+/// with the Tab key bound to the completion menu.
+pub fn edit_mode(keybinds: &KeybindsStyle) -> Box<dyn EditMode> {
+    let completion = ReedlineEvent::UntilFound(vec![
+        ReedlineEvent::Menu("completion".to_string()),
+        ReedlineEvent::MenuNext,
+    ]);
+
+    match keybinds {
+        KeybindsStyle::Emacs => {
+            let mut keybinds = default_emacs_keybindings();
+            keybinds.add_binding(KeyModifiers::NONE, KeyCode::Tab, completion);
+            Box::new(Emacs::new(keybinds))
+        }
+        KeybindsStyle::Vi => {
+            let mut keybinds = default_vi_insert_keybindings();
+            keybinds.add_binding(KeyModifiers::NONE, KeyCode::Tab, completion);
+            Box::new(Vi::new(keybinds, default_vi_normal_keybindings()))
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! In-memory backend config module.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use shellexpand_utils::shellexpand_path;
+
+/// The in-memory backend configuration.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MemoryConfig {
+    /// Directory of `.eml` files loaded when the backend starts.
+    ///
+    /// Files at the root of the directory are added to the inbox,
+    /// and files of sub-directories are added to the folder named
+    /// after the sub-directory.
+    pub seed_dir: Option<PathBuf>,
+}
+
+impl MemoryConfig {
+    pub fn seed_dir(&self) -> Option<PathBuf> {
+        self.seed_dir.as_ref().map(shellexpand_path)
+    }
+}
@@ -0,0 +1,663 @@
+//! In-memory backend module.
+//!
+//! This backend keeps folders and messages in memory, optionally
+//! seeded from a directory of `.eml` files. Changes are lost when the
+//! REPL exits: the backend is meant for tests and demos, which should
+//! not depend on a real IMAP server or Maildir.
+
+pub mod config;
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use email::{
+    account::config::AccountConfig,
+    backend::{
+        context::{BackendContext, BackendContextBuilder},
+        feature::BackendFeature,
+    },
+    envelope::{
+        list::{ListEnvelopes, ListEnvelopesOptions},
+        Envelope, Envelopes, Id, SingleId,
+    },
+    flag::{add::AddFlags, Flag, Flags},
+    folder::{list::ListFolders, Folder, Folders, DRAFTS, INBOX, SENT, TRASH},
+    message::{
+        add::AddMessage, copy::CopyMessages, delete::DeleteMessages, get::GetMessages,
+        peek::PeekMessages, r#move::MoveMessages, Messages,
+    },
+    AnyBoxedError, AnyError, AnyResult,
+};
+use mail_parser::MessageParser;
+use tracing::debug;
+
+use self::config::MemoryConfig;
+
+/// Builds the backend feature matching the given trait, which is
+/// implemented by [`MemoryContextSync`] itself.
+macro_rules! feature {
+    ($feature:ident) => {
+        Some(Arc::new(
+            |ctx: &MemoryContextSync| -> Option<Box<dyn $feature>> { Some(Box::new(ctx.clone())) },
+        ))
+    };
+}
+
+/// Errors of the in-memory backend.
+#[derive(Debug)]
+pub enum Error {
+    FolderNotFound(String),
+    MessageNotFound(String, String),
+    InvalidId(String),
+    ParseMessage(String),
+    ReadSeed(PathBuf, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FolderNotFound(folder) => write!(f, "cannot find folder {folder}"),
+            Self::MessageNotFound(folder, id) => {
+                write!(f, "cannot find message {id} in folder {folder}")
+            }
+            Self::InvalidId(id) => write!(f, "invalid message identifier {id}"),
+            Self::ParseMessage(id) => write!(f, "cannot parse message {id}"),
+            Self::ReadSeed(path, _) => write!(f, "cannot read seed at {path:?}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::ReadSeed(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl AnyError for Error {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl From<Error> for AnyBoxedError {
+    fn from(err: Error) -> Self {
+        Box::new(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct MemoryMessage {
+    raw: Vec<u8>,
+    flags: Flags,
+}
+
+fn parse_ids(id: &Id) -> Result<Vec<usize>> {
+    id.iter()
+        .map(|id| id.parse().map_err(|_| Error::InvalidId(id.to_owned())))
+        .collect()
+}
+
+/// Lists the entries of the given seed directory, sorted by path so
+/// that identifiers are stable across runs.
+fn read_seed_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .map_err(|err| Error::ReadSeed(dir.to_owned(), err))?;
+
+    paths.sort();
+    Ok(paths)
+}
+
+fn is_eml(path: &Path) -> bool {
+    path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("eml")
+}
+
+/// The state of the in-memory backend.
+///
+/// Identifiers are unique across folders: copied and moved messages
+/// get a new one.
+#[derive(Debug, Default)]
+pub struct MemoryContext {
+    folders: BTreeMap<String, BTreeMap<usize, MemoryMessage>>,
+    next_id: usize,
+}
+
+impl MemoryContext {
+    fn folder(&self, folder: &str) -> Result<&BTreeMap<usize, MemoryMessage>> {
+        self.folders
+            .get(folder)
+            .ok_or_else(|| Error::FolderNotFound(folder.to_owned()))
+    }
+
+    fn folder_mut(&mut self, folder: &str) -> Result<&mut BTreeMap<usize, MemoryMessage>> {
+        self.folders
+            .get_mut(folder)
+            .ok_or_else(|| Error::FolderNotFound(folder.to_owned()))
+    }
+
+    /// Adds the given message to the given folder, and returns its
+    /// identifier.
+    fn add(&mut self, folder: &str, raw: Vec<u8>, flags: Flags) -> Result<usize> {
+        let id = self.next_id + 1;
+        self.folder_mut(folder)?
+            .insert(id, MemoryMessage { raw, flags });
+        self.next_id = id;
+        Ok(id)
+    }
+
+    /// Returns the messages of the given folder matching the given
+    /// identifiers, in the same order.
+    fn messages(&self, folder: &str, id: &Id) -> Result<Vec<&MemoryMessage>> {
+        let messages = self.folder(folder)?;
+
+        parse_ids(id)?
+            .into_iter()
+            .map(|id| {
+                messages
+                    .get(&id)
+                    .ok_or_else(|| Error::MessageNotFound(folder.to_owned(), id.to_string()))
+            })
+            .collect()
+    }
+
+    /// Removes the messages of the given folder matching the given
+    /// identifiers, and returns them. Nothing is removed if one of
+    /// them cannot be found.
+    fn take(&mut self, folder: &str, id: &Id) -> Result<Vec<MemoryMessage>> {
+        self.messages(folder, id)?;
+
+        let messages = self.folder_mut(folder)?;
+        let ids = parse_ids(id)?;
+        Ok(ids.iter().filter_map(|id| messages.remove(id)).collect())
+    }
+
+    fn seed_file(&mut self, folder: &str, path: &Path) -> Result<()> {
+        let raw = fs::read(path).map_err(|err| Error::ReadSeed(path.to_owned(), err))?;
+        self.add(folder, raw, Flags::default())?;
+        Ok(())
+    }
+
+    /// Adds the `.eml` files of the given directory to the inbox, and
+    /// the ones of its sub-directories to the matching folders.
+    fn seed(&mut self, dir: &Path, inbox: &str) -> Result<()> {
+        for path in read_seed_dir(dir)? {
+            if is_eml(&path) {
+                self.seed_file(inbox, &path)?;
+                continue;
+            }
+
+            if !path.is_dir() {
+                continue;
+            }
+
+            let Some(folder) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            self.folders.entry(folder.to_owned()).or_default();
+
+            for path in read_seed_dir(&path)? {
+                if is_eml(&path) {
+                    self.seed_file(folder, &path)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The thread-safe in-memory backend context.
+#[derive(Clone)]
+pub struct MemoryContextSync {
+    account_config: Arc<AccountConfig>,
+    inner: Arc<Mutex<MemoryContext>>,
+}
+
+impl MemoryContextSync {
+    fn lock(&self) -> MutexGuard<'_, MemoryContext> {
+        self.inner.lock().unwrap()
+    }
+
+    fn alias(&self, folder: &str) -> String {
+        self.account_config.get_folder_alias(folder)
+    }
+}
+
+impl BackendContext for MemoryContextSync {}
+
+#[async_trait]
+impl ListFolders for MemoryContextSync {
+    async fn list_folders(&self) -> AnyResult<Folders> {
+        let folders = self
+            .lock()
+            .folders
+            .keys()
+            .map(|name| Folder {
+                kind: None,
+                name: name.clone(),
+                desc: String::new(),
+            })
+            .collect();
+
+        Ok(folders)
+    }
+}
+
+#[async_trait]
+impl ListEnvelopes for MemoryContextSync {
+    /// Lists envelopes from the most recent to the oldest one. Search
+    /// queries are not supported.
+    async fn list_envelopes(
+        &self,
+        folder: &str,
+        opts: ListEnvelopesOptions,
+    ) -> AnyResult<Envelopes> {
+        let folder = self.alias(folder);
+        let ctx = self.lock();
+        let mut envelopes = Vec::new();
+
+        for (id, message) in ctx.folder(&folder)? {
+            let parsed = MessageParser::new()
+                .parse(&message.raw)
+                .ok_or_else(|| Error::ParseMessage(id.to_string()))?;
+            envelopes.push(Envelope::from_msg(id, message.flags.clone(), parsed));
+        }
+
+        envelopes.sort_by(|a, b| b.date.cmp(&a.date));
+
+        if opts.page_size > 0 {
+            envelopes = envelopes
+                .into_iter()
+                .skip(opts.page * opts.page_size)
+                .take(opts.page_size)
+                .collect();
+        }
+
+        Ok(Envelopes::from_iter(envelopes))
+    }
+}
+
+#[async_trait]
+impl AddFlags for MemoryContextSync {
+    async fn add_flags(&self, folder: &str, id: &Id, flags: &Flags) -> AnyResult<()> {
+        let folder = self.alias(folder);
+        let mut ctx = self.lock();
+
+        // checks that all messages exist before changing any of them
+        ctx.messages(&folder, id)?;
+
+        let messages = ctx.folder_mut(&folder)?;
+
+        for id in parse_ids(id)? {
+            if let Some(message) = messages.get_mut(&id) {
+                message.flags.extend(flags.iter().cloned());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PeekMessages for MemoryContextSync {
+    async fn peek_messages(&self, folder: &str, id: &Id) -> AnyResult<Messages> {
+        let folder = self.alias(folder);
+        let ctx = self.lock();
+
+        let raws: Vec<Vec<u8>> = ctx
+            .messages(&folder, id)?
+            .into_iter()
+            .map(|message| message.raw.clone())
+            .collect();
+
+        Ok(Messages::from(raws))
+    }
+}
+
+#[async_trait]
+impl GetMessages for MemoryContextSync {
+    async fn get_messages(&self, folder: &str, id: &Id) -> AnyResult<Messages> {
+        let messages = self.peek_messages(folder, id).await?;
+        self.add_flags(folder, id, &Flags::from_iter([Flag::Seen]))
+            .await?;
+        Ok(messages)
+    }
+}
+
+#[async_trait]
+impl AddMessage for MemoryContextSync {
+    async fn add_message_with_flags(
+        &self,
+        folder: &str,
+        raw: &[u8],
+        flags: &Flags,
+    ) -> AnyResult<SingleId> {
+        let folder = self.alias(folder);
+        let id = self.lock().add(&folder, raw.to_vec(), flags.clone())?;
+        Ok(SingleId::from(id.to_string()))
+    }
+}
+
+#[async_trait]
+impl CopyMessages for MemoryContextSync {
+    async fn copy_messages(&self, from_folder: &str, to_folder: &str, id: &Id) -> AnyResult<()> {
+        let (from_folder, to_folder) = (self.alias(from_folder), self.alias(to_folder));
+        let mut ctx = self.lock();
+
+        ctx.folder(&to_folder)?;
+
+        let messages: Vec<MemoryMessage> = ctx
+            .messages(&from_folder, id)?
+            .into_iter()
+            .cloned()
+            .collect();
+
+        for message in messages {
+            ctx.add(&to_folder, message.raw, message.flags)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MoveMessages for MemoryContextSync {
+    async fn move_messages(&self, from_folder: &str, to_folder: &str, id: &Id) -> AnyResult<()> {
+        let (from_folder, to_folder) = (self.alias(from_folder), self.alias(to_folder));
+        let mut ctx = self.lock();
+
+        ctx.folder(&to_folder)?;
+
+        for message in ctx.take(&from_folder, id)? {
+            ctx.add(&to_folder, message.raw, message.flags)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeleteMessages for MemoryContextSync {
+    /// Moves messages to the trash folder, or removes them for good
+    /// when they already are in the trash folder.
+    async fn delete_messages(&self, folder: &str, id: &Id) -> AnyResult<()> {
+        let (folder, trash) = (self.alias(folder), self.alias(TRASH));
+        let mut ctx = self.lock();
+        let messages = ctx.take(&folder, id)?;
+
+        if folder != trash {
+            for message in messages {
+                ctx.add(&trash, message.raw, message.flags)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct MemoryContextBuilder {
+    account_config: Arc<AccountConfig>,
+    config: Arc<MemoryConfig>,
+}
+
+impl MemoryContextBuilder {
+    pub fn new(account_config: Arc<AccountConfig>, config: Arc<MemoryConfig>) -> Self {
+        Self {
+            account_config,
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl BackendContextBuilder for MemoryContextBuilder {
+    type Context = MemoryContextSync;
+
+    fn list_folders(&self) -> Option<BackendFeature<Self::Context, dyn ListFolders>> {
+        feature!(ListFolders)
+    }
+
+    fn list_envelopes(&self) -> Option<BackendFeature<Self::Context, dyn ListEnvelopes>> {
+        feature!(ListEnvelopes)
+    }
+
+    fn add_flags(&self) -> Option<BackendFeature<Self::Context, dyn AddFlags>> {
+        feature!(AddFlags)
+    }
+
+    fn peek_messages(&self) -> Option<BackendFeature<Self::Context, dyn PeekMessages>> {
+        feature!(PeekMessages)
+    }
+
+    fn get_messages(&self) -> Option<BackendFeature<Self::Context, dyn GetMessages>> {
+        feature!(GetMessages)
+    }
+
+    fn add_message(&self) -> Option<BackendFeature<Self::Context, dyn AddMessage>> {
+        feature!(AddMessage)
+    }
+
+    fn copy_messages(&self) -> Option<BackendFeature<Self::Context, dyn CopyMessages>> {
+        feature!(CopyMessages)
+    }
+
+    fn move_messages(&self) -> Option<BackendFeature<Self::Context, dyn MoveMessages>> {
+        feature!(MoveMessages)
+    }
+
+    fn delete_messages(&self) -> Option<BackendFeature<Self::Context, dyn DeleteMessages>> {
+        feature!(DeleteMessages)
+    }
+
+    async fn build(self) -> AnyResult<Self::Context> {
+        let mut ctx = MemoryContext::default();
+
+        for folder in [INBOX, SENT, DRAFTS, TRASH] {
+            let folder = self.account_config.get_folder_alias(folder);
+            ctx.folders.entry(folder).or_default();
+        }
+
+        if let Some(dir) = self.config.seed_dir() {
+            debug!("seeding in-memory backend from {dir:?}");
+            let inbox = self.account_config.get_folder_alias(INBOX);
+            ctx.seed(&dir, &inbox)?;
+        }
+
+        Ok(MemoryContextSync {
+            account_config: self.account_config,
+            inner: Arc::new(Mutex::new(ctx)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, sync::Arc};
+
+    use email::{
+        account::config::AccountConfig,
+        backend::context::BackendContextBuilder,
+        envelope::{
+            list::{ListEnvelopes, ListEnvelopesOptions},
+            Id,
+        },
+        flag::{add::AddFlags, Flag, Flags},
+        folder::{list::ListFolders, INBOX, SENT, TRASH},
+        message::{
+            add::AddMessage, copy::CopyMessages, delete::DeleteMessages, get::GetMessages,
+            peek::PeekMessages, r#move::MoveMessages,
+        },
+    };
+    use uuid::Uuid;
+
+    use super::{config::MemoryConfig, MemoryContextBuilder, MemoryContextSync};
+
+    fn email(subject: &str) -> Vec<u8> {
+        format!(
+            "Message-ID: <{subject}@localhost>\r\n\
+             From: alice@localhost\r\n\
+             To: bob@localhost\r\n\
+             Subject: {subject}\r\n\
+             Date: Sun, 18 Oct 2026 09:00:00 +0000\r\n\
+             \r\n\
+             Hello!\r\n"
+        )
+        .into_bytes()
+    }
+
+    async fn context(seed_dir: Option<PathBuf>) -> MemoryContextSync {
+        let account_config = Arc::new(AccountConfig::default());
+        let config = Arc::new(MemoryConfig { seed_dir });
+        MemoryContextBuilder::new(account_config, config)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    async fn subjects(ctx: &MemoryContextSync, folder: &str) -> Vec<String> {
+        ctx.list_envelopes(folder, ListEnvelopesOptions::default())
+            .await
+            .unwrap()
+            .iter()
+            .map(|envelope| envelope.subject.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn seed_dir() {
+        let dir = std::env::temp_dir().join(format!("himalaya-memory-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("Archive")).unwrap();
+        fs::write(dir.join("first.eml"), email("first")).unwrap();
+        fs::write(dir.join("notes.txt"), "not an email").unwrap();
+        fs::write(dir.join("Archive").join("second.eml"), email("second")).unwrap();
+
+        let ctx = context(Some(dir.clone())).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        let folders = ctx.list_folders().await.unwrap();
+        assert!(folders.iter().any(|folder| folder.name == "Archive"));
+
+        assert_eq!(subjects(&ctx, INBOX).await, vec!["first"]);
+        assert_eq!(subjects(&ctx, "Archive").await, vec!["second"]);
+    }
+
+    #[tokio::test]
+    async fn add_peek_and_get() {
+        let ctx = context(None).await;
+
+        let id = ctx.add_message(INBOX, &email("hello")).await.unwrap();
+        let id = Id::single(id.to_string());
+
+        let messages = ctx.peek_messages(INBOX, &id).await.unwrap();
+        assert_eq!(messages.first().unwrap().raw().unwrap(), email("hello"));
+
+        let envelopes = ctx
+            .list_envelopes(INBOX, ListEnvelopesOptions::default())
+            .await
+            .unwrap();
+        assert!(!envelopes.first().unwrap().flags.contains(&Flag::Seen));
+
+        ctx.get_messages(INBOX, &id).await.unwrap();
+
+        let envelopes = ctx
+            .list_envelopes(INBOX, ListEnvelopesOptions::default())
+            .await
+            .unwrap();
+        assert!(envelopes.first().unwrap().flags.contains(&Flag::Seen));
+    }
+
+    #[tokio::test]
+    async fn add_flags() {
+        let ctx = context(None).await;
+
+        let id = ctx.add_message(INBOX, &email("hello")).await.unwrap();
+        let id = Id::single(id.to_string());
+        let flags = Flags::from_iter([Flag::Flagged, Flag::Answered]);
+        ctx.add_flags(INBOX, &id, &flags).await.unwrap();
+
+        let envelopes = ctx
+            .list_envelopes(INBOX, ListEnvelopesOptions::default())
+            .await
+            .unwrap();
+        let envelope = envelopes.first().unwrap();
+        assert!(envelope.flags.contains(&Flag::Flagged));
+        assert!(envelope.flags.contains(&Flag::Answered));
+    }
+
+    #[tokio::test]
+    async fn copy_move_and_delete() {
+        let ctx = context(None).await;
+
+        let id = ctx.add_message(INBOX, &email("hello")).await.unwrap();
+        let id = Id::single(id.to_string());
+
+        ctx.copy_messages(INBOX, SENT, &id).await.unwrap();
+        assert_eq!(subjects(&ctx, INBOX).await, vec!["hello"]);
+        assert_eq!(subjects(&ctx, SENT).await, vec!["hello"]);
+
+        ctx.move_messages(INBOX, TRASH, &id).await.unwrap();
+        assert!(subjects(&ctx, INBOX).await.is_empty());
+        assert_eq!(subjects(&ctx, TRASH).await, vec!["hello"]);
+
+        // deleted messages go to the trash first
+        let envelopes = ctx
+            .list_envelopes(SENT, ListEnvelopesOptions::default())
+            .await
+            .unwrap();
+        let id = Id::single(envelopes.first().unwrap().id.clone());
+        ctx.delete_messages(SENT, &id).await.unwrap();
+        assert!(subjects(&ctx, SENT).await.is_empty());
+        assert_eq!(subjects(&ctx, TRASH).await, vec!["hello", "hello"]);
+
+        // then they are removed for good
+        let envelopes = ctx
+            .list_envelopes(TRASH, ListEnvelopesOptions::default())
+            .await
+            .unwrap();
+        for envelope in envelopes.iter() {
+            let id = Id::single(envelope.id.clone());
+            ctx.delete_messages(TRASH, &id).await.unwrap();
+        }
+        assert!(subjects(&ctx, TRASH).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let ctx = context(None).await;
+
+        let id = ctx.add_message(INBOX, &email("hello")).await.unwrap();
+        let id = Id::single(id.to_string());
+
+        assert!(ctx
+            .list_envelopes("Unknown", Default::default())
+            .await
+            .is_err());
+        assert!(ctx.add_message("Unknown", &email("hello")).await.is_err());
+        assert!(ctx.move_messages(INBOX, "Unknown", &id).await.is_err());
+        assert!(ctx.peek_messages(INBOX, &Id::single("42")).await.is_err());
+        assert!(ctx
+            .peek_messages(INBOX, &Id::single("invalid"))
+            .await
+            .is_err());
+
+        // failed moves leave the message in place
+        assert_eq!(subjects(&ctx, INBOX).await, vec!["hello"]);
+    }
+}
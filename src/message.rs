@@ -0,0 +1,153 @@
+use chrono::Local;
+use color_eyre::Result;
+use email::{
+    account::config::AccountConfig,
+    message::{Message, Messages},
+};
+use mail_parser::MessageParser;
+use uuid::Uuid;
+
+use crate::{pgp, reader::Reader};
+
+/// Renders the given messages to read templates, separated by an
+/// empty line. Signed and encrypted messages are preceded by their
+/// PGP status, and templates are rendered with the given reader.
+pub async fn to_read_tpls(
+    config: &AccountConfig,
+    messages: &Messages,
+    reader: &Reader,
+) -> Result<String> {
+    let mut glue = "";
+    let mut bodies = String::default();
+
+    for message in messages.to_vec() {
+        bodies.push_str(glue);
+
+        let mut decrypted = None;
+
+        if let Some((status, raw)) = pgp::status(config, message.raw()?).await {
+            bodies.push_str(&status.to_string());
+            bodies.push('\n');
+            decrypted = raw.map(Message::from);
+        }
+
+        // decrypted messages are rendered as is, which prevents the
+        // template interpreter from decrypting them a second time
+        let tpl = decrypted
+            .as_ref()
+            .unwrap_or(message)
+            .to_read_tpl(config, |tpl| tpl)
+            .await?;
+        bodies.push_str(&reader.render(&tpl));
+
+        glue = "\n\n";
+    }
+
+    Ok(bodies)
+}
+
+/// Turns the given raw message into a message redirected to the given
+/// address, as defined in RFC5322 section 3.6.6.
+///
+/// The original message is kept byte for byte, a block of Resent-*
+/// headers is only prepended to it. Since its headers still name the
+/// original recipients, the message must be sent to the redirection
+/// address as envelope recipient, see [`send_message_to`].
+///
+/// [`send_message_to`]: crate::backend::send_message_to
+pub fn to_bounce(config: &AccountConfig, raw: &[u8], to: &str) -> Vec<u8> {
+    // resent headers use the line endings of the original message
+    let eol = match raw.iter().position(|&b| b == b'\n') {
+        Some(i) if i > 0 && raw[i - 1] == b'\r' => "\r\n",
+        _ => "\n",
+    };
+
+    let domain = config.email.rsplit('@').next().unwrap_or("localhost");
+    let from = match &config.display_name {
+        Some(name) => format!("\"{}\" <{}>", name.replace('"', ""), config.email),
+        None => config.email.clone(),
+    };
+
+    let mut bounce = Vec::with_capacity(raw.len() + 256);
+    let mut push_header = |key: &str, val: &str| {
+        bounce.extend_from_slice(format!("{key}: {val}{eol}").as_bytes());
+    };
+
+    push_header("Resent-From", &from);
+    push_header("Resent-Date", &Local::now().to_rfc2822());
+    push_header("Resent-To", to);
+    push_header(
+        "Resent-Message-ID",
+        &format!("<{}@{domain}>", Uuid::new_v4()),
+    );
+
+    bounce.extend_from_slice(raw);
+    bounce
+}
+
+/// Parses the given address the way addresses of message headers
+/// are parsed, with or without display name.
+///
+/// Returns the bare address, or `None` if the input does not hold
+/// exactly one address.
+pub fn parse_address(input: &str) -> Option<String> {
+    let header = format!("To: {input}\r\n\r\n");
+    let message = MessageParser::new().parse(header.as_bytes())?;
+
+    match message.to()?.iter().collect::<Vec<_>>().as_slice() {
+        [addr] => addr
+            .address()
+            .filter(|addr| addr.contains('@'))
+            .map(ToOwned::to_owned),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use email::account::config::AccountConfig;
+
+    use super::{parse_address, to_bounce};
+
+    #[test]
+    fn bounce() {
+        let config = AccountConfig {
+            email: String::from("alice@localhost"),
+            ..Default::default()
+        };
+
+        let raw = b"To: bob@localhost\r\nCc: carol@localhost\r\n\r\nBody \xe9\r\n";
+        let bounce = to_bounce(&config, raw, "dave@localhost");
+
+        // the original message is kept untouched
+        assert!(bounce.ends_with(raw));
+
+        let headers = String::from_utf8_lossy(&bounce[..bounce.len() - raw.len()]);
+        let names: Vec<&str> = headers
+            .split_terminator("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, _)| name)
+            .collect();
+
+        assert_eq!(
+            names,
+            [
+                "Resent-From",
+                "Resent-Date",
+                "Resent-To",
+                "Resent-Message-ID"
+            ]
+        );
+        assert!(headers.contains("Resent-From: alice@localhost\r\n"));
+        assert!(headers.contains("Resent-To: dave@localhost\r\n"));
+    }
+
+    #[test]
+    fn address() {
+        let addr = Some(String::from("bob@localhost"));
+        assert_eq!(parse_address("bob@localhost"), addr);
+        assert_eq!(parse_address("Bob <bob@localhost>"), addr);
+        assert_eq!(parse_address("bob"), None);
+        assert_eq!(parse_address("bob@localhost, carol@localhost"), None);
+    }
+}
@@ -0,0 +1,391 @@
+//! Offline module.
+//!
+//! When the REPL runs offline, either on demand or because IMAP is
+//! unreachable, the backend reads and writes the local Maildir cache
+//! of the [`Synchronizer`] instead. Moves, deletions and flag changes
+//! are applied to the cache and recorded in a persistent journal,
+//! which is replayed against IMAP once the connection is back.
+//!
+//! Messages are identified by their Message-ID header in the journal,
+//! since the identifiers of the cache and the ones of IMAP differ.
+
+use std::{collections::HashMap, fmt, fs, path::PathBuf, sync::Arc, time::SystemTime};
+
+use chrono::{DateTime, Local};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use comfy_table::{presets, Cell, ContentArrangement, Row, Table};
+use dirs::data_dir;
+use email::{
+    account::config::AccountConfig,
+    backend::BackendBuilder,
+    envelope::{
+        list::{ListEnvelopes, ListEnvelopesOptions},
+        Id,
+    },
+    flag::add::AddFlags,
+    message::{delete::DeleteMessages, peek::PeekMessages, r#move::MoveMessages, Message},
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    backend::{Backend, BackendKind, ContextBuilder},
+    envelope::Flag,
+    sync::Synchronizer,
+};
+
+/// Builds a backend reading and writing the local Maildir cache of
+/// the given synchronizer instead of IMAP, for all features. The
+/// sending backend stays the same.
+pub async fn build_offline_backend(
+    account_config: Arc<AccountConfig>,
+    ctx: ContextBuilder,
+    sync: &Synchronizer,
+) -> Result<Backend> {
+    if !sync.dir().exists() {
+        let dir = sync.dir();
+        return Err(eyre!(
+            "cannot find local cache at {dir:?}, please synchronize the account first"
+        ));
+    }
+
+    let ctx = ContextBuilder {
+        backend: BackendKind::Maildir,
+        routes: Default::default(),
+        maildir: Some(sync.maildir_context()),
+        imap: None,
+        ..ctx
+    };
+
+    let backend = BackendBuilder::new(account_config, ctx).build().await?;
+    Ok(backend)
+}
+
+/// Removes the angle brackets around the given Message-ID, which are
+/// kept by some backends only.
+fn normalize_message_id(message_id: &str) -> &str {
+    message_id
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum JournalOp {
+    Move { target: String },
+    Delete,
+    AddFlag { flag: Flag },
+}
+
+impl fmt::Display for JournalOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Move { target } => write!(f, "move to {target}"),
+            Self::Delete => write!(f, "delete"),
+            Self::AddFlag { flag } => match flag {
+                Flag::Custom(flag) => write!(f, "add flag {flag}"),
+                flag => write!(f, "add flag {flag:?}"),
+            },
+        }
+    }
+}
+
+/// An operation applied to the local cache while offline.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct JournalEntry {
+    pub id: String,
+    pub folder: String,
+    pub message_id: String,
+    pub subject: String,
+    pub op: JournalOp,
+    pub created_at: SystemTime,
+}
+
+impl JournalEntry {
+    /// Creates a journal entry for the given message of the local
+    /// cache.
+    pub fn from_message(folder: &str, message: &Message, op: JournalOp) -> Result<Self> {
+        let parsed = message.parsed()?;
+
+        let message_id = parsed
+            .message_id()
+            .ok_or(eyre!("cannot journal message without Message-ID header"))?;
+
+        Ok(Self {
+            id: Uuid::new_v4().to_string()[..8].to_owned(),
+            folder: folder.to_owned(),
+            message_id: normalize_message_id(message_id).to_owned(),
+            subject: parsed.subject().unwrap_or_default().to_owned(),
+            op,
+            created_at: SystemTime::now(),
+        })
+    }
+
+    /// Creates a journal entry for the message matching the given
+    /// identifier in the local cache. This needs to be done before
+    /// applying the operation, since moved and deleted messages
+    /// cannot be peeked anymore.
+    pub async fn new(backend: &Backend, folder: &str, id: &Id, op: JournalOp) -> Result<Self> {
+        let messages = backend.peek_messages(folder, id).await?;
+        let message = messages
+            .first()
+            .ok_or_else(|| eyre!("cannot find message {id} in folder {folder}"))?;
+        Self::from_message(folder, message, op)
+    }
+
+    /// Applies the operation to the given IMAP envelope identifier.
+    async fn apply(&self, backend: &Backend, id: &str) -> Result<()> {
+        let folder = &self.folder;
+        let id = Id::single(id);
+
+        match &self.op {
+            JournalOp::Move { target } => backend.move_messages(folder, target, &id).await?,
+            JournalOp::Delete => backend.delete_messages(folder, &id).await?,
+            JournalOp::AddFlag { flag } => backend.add_flag(folder, &id, flag.into()).await?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Outcome of a replay of the journal.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReplayReport {
+    pub applied: usize,
+    /// Operations which cannot apply anymore, because their message
+    /// changed on the server in the meantime. They are dropped from
+    /// the journal.
+    pub conflicts: Vec<String>,
+    /// Operations which failed, and which are kept in the journal
+    /// for the next replay.
+    pub failures: Vec<String>,
+}
+
+impl ReplayReport {
+    /// Summarizes the given replay in a single line.
+    pub fn to_line(&self) -> String {
+        format!(
+            "Replayed offline changes: {} applied, {} conflict(s), {} failure(s)",
+            self.applied,
+            self.conflicts.len(),
+            self.failures.len()
+        )
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.to_line())?;
+
+        for conflict in &self.conflicts {
+            writeln!(f, "Conflict: {conflict}")?;
+        }
+
+        for failure in &self.failures {
+            writeln!(f, "Failure: {failure}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The journal of operations applied offline to the local cache of
+/// an account.
+#[derive(Clone, Debug)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new(account_config: &AccountConfig) -> Result<Self> {
+        let path = data_dir()
+            .ok_or(eyre!("cannot get XDG data directory"))?
+            .join("himalaya")
+            .join("journal")
+            .join(format!("{}.json", account_config.name));
+
+        Ok(Self::from_path(path))
+    }
+
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Lists journal entries, from the oldest to the most recent one.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>> {
+        let path = &self.path;
+
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let entries = fs::read(path).context(format!("cannot read journal at {path:?}"))?;
+        let entries = serde_json::from_slice(&entries)
+            .context(format!("cannot parse journal at {path:?}"))?;
+
+        Ok(entries)
+    }
+
+    fn save(&self, entries: &[JournalEntry]) -> Result<()> {
+        let path = &self.path;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .context(format!("cannot create journal directory at {dir:?}"))?;
+        }
+
+        let entries = serde_json::to_vec_pretty(entries)?;
+        fs::write(path, entries).context(format!("cannot write journal at {path:?}"))
+    }
+
+    pub fn push(&self, entry: JournalEntry) -> Result<()> {
+        debug!("journaling offline operation {}: {}", entry.id, entry.op);
+
+        let mut entries = self.entries()?;
+        entries.push(entry);
+        self.save(&entries)
+    }
+
+    /// Removes the entry matching the given identifier, without
+    /// replaying it. Returns `None` if there is no such entry.
+    pub fn discard(&self, id: &str) -> Result<Option<JournalEntry>> {
+        let mut entries = self.entries()?;
+
+        let Some(pos) = entries.iter().position(|entry| entry.id == id) else {
+            return Ok(None);
+        };
+
+        let entry = entries.remove(pos);
+        self.save(&entries)?;
+        Ok(Some(entry))
+    }
+
+    /// Replays journal entries against the given IMAP backend, in the
+    /// order they were recorded.
+    ///
+    /// Messages are looked up by Message-ID in their folder. Entries
+    /// whose message cannot be found are reported as conflicts, and
+    /// entries which fail are kept for the next replay.
+    pub async fn replay(&self, backend: &Backend) -> Result<ReplayReport> {
+        let mut report = ReplayReport::default();
+        let mut remaining = Vec::new();
+
+        // Message-ID to envelope identifier, by folder
+        let mut folders: HashMap<String, HashMap<String, String>> = HashMap::new();
+
+        for entry in self.entries()? {
+            let subject = &entry.subject;
+            let folder = &entry.folder;
+
+            if !folders.contains_key(folder) {
+                let opts = ListEnvelopesOptions {
+                    page_size: 0,
+                    ..Default::default()
+                };
+
+                match backend.list_envelopes(folder, opts).await {
+                    Ok(envelopes) => {
+                        let ids = envelopes
+                            .iter()
+                            .map(|envelope| {
+                                let message_id = normalize_message_id(&envelope.message_id);
+                                (message_id.to_owned(), envelope.id.clone())
+                            })
+                            .collect();
+                        folders.insert(folder.clone(), ids);
+                    }
+                    Err(err) => {
+                        let op = &entry.op;
+                        report.failures.push(format!(
+                            "cannot {op} \"{subject}\", cannot list folder {folder}: {err}"
+                        ));
+                        remaining.push(entry);
+                        continue;
+                    }
+                }
+            }
+
+            let Some(id) = folders[folder].get(&entry.message_id).cloned() else {
+                report.conflicts.push(format!(
+                    "cannot {} \"{subject}\", it has been moved or deleted from {folder} on the server",
+                    entry.op
+                ));
+                continue;
+            };
+
+            if let Err(err) = entry.apply(backend, &id).await {
+                report
+                    .failures
+                    .push(format!("cannot {} \"{subject}\": {err}", entry.op));
+                remaining.push(entry);
+                continue;
+            }
+
+            report.applied += 1;
+
+            // identifiers of the affected folders changed
+            if let JournalOp::Move { target } = &entry.op {
+                folders.remove(target);
+            }
+
+            if !matches!(entry.op, JournalOp::AddFlag { .. }) {
+                folders.remove(folder);
+            }
+        }
+
+        self.save(&remaining)?;
+        Ok(report)
+    }
+}
+
+fn format_system_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+pub struct JournalTable(Vec<JournalEntry>);
+
+impl From<Vec<JournalEntry>> for JournalTable {
+    fn from(entries: Vec<JournalEntry>) -> Self {
+        Self(entries)
+    }
+}
+
+impl fmt::Display for JournalTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+
+        table
+            .load_preset(presets::ASCII_MARKDOWN)
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_header(Row::from([
+                Cell::new("ID"),
+                Cell::new("FOLDER"),
+                Cell::new("SUBJECT"),
+                Cell::new("OPERATION"),
+                Cell::new("DATE"),
+            ]))
+            .add_rows(self.0.iter().map(|entry| {
+                let mut row = Row::new();
+                row.max_height(1);
+                row.add_cell(Cell::new(&entry.id))
+                    .add_cell(Cell::new(&entry.folder))
+                    .add_cell(Cell::new(&entry.subject))
+                    .add_cell(Cell::new(&entry.op))
+                    .add_cell(Cell::new(format_system_time(entry.created_at)));
+                row
+            }));
+
+        writeln!(f)?;
+        write!(f, "{table}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
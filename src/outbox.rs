@@ -0,0 +1,283 @@
+//! Outbox module.
+//!
+//! The outbox is a directory holding compiled messages waiting to be
+//! sent, either because they are scheduled for later or because their
+//! sending failed. Each message comes with a small metadata file
+//! keeping track of its schedule and of its last sending error.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use comfy_table::{presets, Cell, ContentArrangement, Row, Table};
+use dirs::data_dir;
+use email::account::config::AccountConfig;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Default interval between two flushes of the outbox, in seconds.
+pub const DEFAULT_OUTBOX_INTERVAL: u64 = 60;
+
+/// Date formats accepted by [`parse_send_at`].
+static SEND_AT_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+];
+
+/// Parses the given local date and time, like `2026-10-18T09:00`.
+pub fn parse_send_at(input: &str) -> Result<SystemTime> {
+    let input = input.trim();
+
+    let date = SEND_AT_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        .ok_or_else(|| eyre!("cannot parse date {input:?}, expected YYYY-MM-DDTHH:MM"))?;
+
+    let date = Local
+        .from_local_datetime(&date)
+        .earliest()
+        .ok_or_else(|| eyre!("date {input:?} does not exist in the local timezone"))?;
+
+    Ok(date.into())
+}
+
+fn format_system_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct OutboxMetadata {
+    subject: String,
+    created_at: SystemTime,
+    send_at: Option<SystemTime>,
+    #[serde(default)]
+    attempts: u32,
+    #[serde(default)]
+    last_error: Option<String>,
+}
+
+/// A compiled message waiting in the outbox.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutboxMessage {
+    pub id: String,
+    pub subject: String,
+    pub created_at: SystemTime,
+    /// The date the message should be sent at, or `None` to send it
+    /// as soon as possible.
+    pub send_at: Option<SystemTime>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub path: PathBuf,
+}
+
+impl OutboxMessage {
+    fn metadata_path(&self) -> PathBuf {
+        self.path.with_extension("json")
+    }
+
+    fn write_metadata(&self) -> Result<()> {
+        let metadata = OutboxMetadata {
+            subject: self.subject.clone(),
+            created_at: self.created_at,
+            send_at: self.send_at,
+            attempts: self.attempts,
+            last_error: self.last_error.clone(),
+        };
+        let metadata = serde_json::to_vec_pretty(&metadata)?;
+        let path = self.metadata_path();
+        fs::write(&path, metadata).context(format!("cannot write outbox message at {path:?}"))
+    }
+
+    /// Returns `true` if the message should be sent by now.
+    pub fn is_due(&self) -> bool {
+        match self.send_at {
+            Some(send_at) => send_at <= SystemTime::now(),
+            None => true,
+        }
+    }
+
+    pub fn read(&self) -> Result<Vec<u8>> {
+        let path = &self.path;
+        fs::read(path).context(format!("cannot read outbox message at {path:?}"))
+    }
+
+    /// Keeps track of a failed sending attempt.
+    pub fn record_failure(&mut self, err: impl ToString) -> Result<()> {
+        self.attempts += 1;
+        self.last_error = Some(err.to_string());
+        self.write_metadata()
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        debug!("removing outbox message {}", self.id);
+
+        for path in [&self.path, &self.metadata_path()] {
+            if path.exists() {
+                fs::remove_file(path)
+                    .context(format!("cannot remove outbox message at {path:?}"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The outbox directory of an account.
+#[derive(Clone, Debug)]
+pub struct Outbox {
+    dir: PathBuf,
+}
+
+impl Outbox {
+    pub fn new(account_config: &AccountConfig) -> Result<Self> {
+        let dir = data_dir()
+            .ok_or(eyre!("cannot get XDG data directory"))?
+            .join("himalaya")
+            .join("outbox")
+            .join(&account_config.name);
+
+        Self::from_dir(dir)
+    }
+
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("cannot create outbox directory at {dir:?}"))?;
+
+        Ok(Self { dir })
+    }
+
+    /// Stores the given compiled message in the outbox.
+    pub fn push(
+        &self,
+        subject: impl ToString,
+        email: &[u8],
+        send_at: Option<SystemTime>,
+    ) -> Result<OutboxMessage> {
+        let id = Uuid::new_v4().to_string()[..8].to_owned();
+
+        let message = OutboxMessage {
+            path: self.dir.join(&id).with_extension("eml"),
+            id,
+            subject: subject.to_string(),
+            created_at: SystemTime::now(),
+            send_at,
+            attempts: 0,
+            last_error: None,
+        };
+
+        let path = &message.path;
+        fs::write(path, email).context(format!("cannot write outbox message at {path:?}"))?;
+        message.write_metadata()?;
+
+        debug!("pushed message {} to the outbox", message.id);
+        Ok(message)
+    }
+
+    /// Lists outbox messages, from the oldest to the most recent one.
+    pub fn list(&self) -> Result<Vec<OutboxMessage>> {
+        let entries = fs::read_dir(&self.dir)
+            .with_context(|| format!("cannot read outbox directory at {:?}", self.dir))?;
+
+        let mut messages = Vec::new();
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let metadata = fs::read(&path)
+                .with_context(|| format!("cannot read outbox message at {path:?}"))?;
+            let metadata: OutboxMetadata = match serde_json::from_slice(&metadata) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    debug!("skipping invalid outbox message at {path:?}: {err}");
+                    continue;
+                }
+            };
+
+            messages.push(OutboxMessage {
+                id: id.to_owned(),
+                subject: metadata.subject,
+                created_at: metadata.created_at,
+                send_at: metadata.send_at,
+                attempts: metadata.attempts,
+                last_error: metadata.last_error,
+                path: path.with_extension("eml"),
+            })
+        }
+
+        messages.sort_by_key(|message| message.created_at);
+        Ok(messages)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<OutboxMessage>> {
+        let message = self.list()?.into_iter().find(|message| message.id == id);
+        Ok(message)
+    }
+}
+
+pub struct OutboxTable(Vec<OutboxMessage>);
+
+impl From<Vec<OutboxMessage>> for OutboxTable {
+    fn from(messages: Vec<OutboxMessage>) -> Self {
+        Self(messages)
+    }
+}
+
+impl fmt::Display for OutboxTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+
+        table
+            .load_preset(presets::ASCII_MARKDOWN)
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_header(Row::from([
+                Cell::new("ID"),
+                Cell::new("SUBJECT"),
+                Cell::new("SEND AT"),
+                Cell::new("ATTEMPTS"),
+                Cell::new("LAST ERROR"),
+            ]))
+            .add_rows(self.0.iter().map(|message| {
+                let send_at = match message.send_at {
+                    Some(send_at) => format_system_time(send_at),
+                    None => String::from("asap"),
+                };
+
+                let mut row = Row::new();
+                row.max_height(1);
+                row.add_cell(Cell::new(&message.id))
+                    .add_cell(Cell::new(&message.subject))
+                    .add_cell(Cell::new(send_at))
+                    .add_cell(Cell::new(message.attempts))
+                    .add_cell(Cell::new(message.last_error.as_deref().unwrap_or_default()));
+                row
+            }));
+
+        writeln!(f)?;
+        write!(f, "{table}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+//! Pager module.
+//!
+//! This module pipes long contents into the user's pager.
+
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use color_eyre::{eyre::Context, Result};
+
+static DEFAULT_PAGER: &str = "less -R";
+
+/// Shows the given content in the pager defined by the `PAGER`
+/// environment variable, defaulting to `less`.
+///
+/// The content is written to the given output if the pager cannot be
+/// spawned.
+pub fn page(out: &mut dyn Write, content: &str) -> Result<()> {
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_owned());
+
+    let args = shlex::split(&pager).unwrap_or_default();
+
+    let Some((program, args)) = args.split_first() else {
+        writeln!(out, "{content}")?;
+        return Ok(());
+    };
+
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            tracing::debug!("cannot spawn pager {pager}: {err}");
+            writeln!(out, "{content}")?;
+            return Ok(());
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pager may exit before reading the whole content
+        let _ = stdin.write_all(content.as_bytes());
+    }
+
+    child
+        .wait()
+        .with_context(|| format!("cannot wait for pager {pager}"))?;
+
+    Ok(())
+}
@@ -0,0 +1,425 @@
+//! PGP module.
+//!
+//! This module detects PGP/MIME signed and encrypted messages, as
+//! defined in RFC3156, and checks them with the PGP backend of the
+//! account in order to show a status banner above their body.
+//! Decrypted bodies are handed over to the template interpreter, so
+//! that messages are decrypted only once.
+//!
+//! It also wraps template bodies in MML tags signing or encrypting
+//! them, and checks that recipients keys are available beforehand.
+
+use std::fmt;
+
+use color_eyre::{eyre::eyre, Result};
+use email::account::config::AccountConfig;
+use mail_parser::{Message, MessageParser, MessagePart, MimeHeaders, PartType};
+
+/// MML property signing a part.
+static SIGN_PROP: &str = "sign=pgpmime";
+
+/// MML property encrypting a part.
+static ENCRYPT_PROP: &str = "encrypt=pgpmime";
+
+/// MML closing tag of the wrapper.
+static CLOSING_TAG: &str = "<#/multipart>";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SignatureStatus {
+    Valid,
+    /// The public key of the signer cannot be found.
+    UnknownKey(String),
+    Bad,
+    /// The signature cannot be checked for another reason.
+    Error(String),
+    /// The signature cannot be checked, because PGP is not
+    /// configured for the current account.
+    Unchecked,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EncryptionStatus {
+    Decrypted,
+    Undecryptable(String),
+    /// The message cannot be decrypted, because PGP is not configured
+    /// for the current account.
+    Unchecked,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PgpStatus {
+    pub signer: String,
+    pub encryption: Option<EncryptionStatus>,
+    pub signature: Option<SignatureStatus>,
+}
+
+impl fmt::Display for PgpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signer = &self.signer;
+
+        match &self.encryption {
+            None => (),
+            Some(EncryptionStatus::Decrypted) => {
+                writeln!(f, "[PGP] Encrypted message, successfully decrypted")?
+            }
+            Some(EncryptionStatus::Undecryptable(err)) => {
+                writeln!(f, "[PGP] Encrypted message, cannot decrypt it: {err}")?
+            }
+            Some(EncryptionStatus::Unchecked) => {
+                writeln!(f, "[PGP] Encrypted message, PGP is not configured")?
+            }
+        }
+
+        match &self.signature {
+            None => (),
+            Some(SignatureStatus::Valid) => writeln!(f, "[PGP] Good signature from {signer}")?,
+            Some(SignatureStatus::UnknownKey(err)) => writeln!(
+                f,
+                "[PGP] Signature from {signer} cannot be verified, unknown key: {err}"
+            )?,
+            Some(SignatureStatus::Bad) => writeln!(f, "[PGP] BAD signature from {signer}!")?,
+            Some(SignatureStatus::Error(err)) => {
+                writeln!(f, "[PGP] Signature from {signer} cannot be verified: {err}")?
+            }
+            Some(SignatureStatus::Unchecked) => {
+                writeln!(f, "[PGP] Signed by {signer}, PGP is not configured")?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the signature and the encryption of the given raw message.
+///
+/// Returns `None` if the message is neither signed nor encrypted.
+/// Successfully decrypted messages are returned along with their
+/// status, see [`with_decrypted_body`].
+pub async fn status(config: &AccountConfig, raw: &[u8]) -> Option<(PgpStatus, Option<Vec<u8>>)> {
+    let message = MessageParser::new().parse(raw)?;
+
+    let mut status = PgpStatus {
+        signer: sender(&message).unwrap_or_default(),
+        ..Default::default()
+    };
+
+    if message.is_content_type("multipart", "encrypted") {
+        let Some((_, encrypted)) = subparts(&message) else {
+            let err = String::from("invalid PGP/MIME structure");
+            status.encryption = Some(EncryptionStatus::Undecryptable(err));
+            return Some((status, None));
+        };
+
+        let decrypted = match decrypt(config, encrypted.contents()).await {
+            None => {
+                status.encryption = Some(EncryptionStatus::Unchecked);
+                None
+            }
+            Some(Err(err)) => {
+                status.encryption = Some(EncryptionStatus::Undecryptable(err));
+                None
+            }
+            Some(Ok(decrypted)) => {
+                status.encryption = Some(EncryptionStatus::Decrypted);
+                // signed then encrypted messages hold a signed part
+                status.signature = signature_status(config, &decrypted, &status.signer).await;
+                Some(with_decrypted_body(raw, &decrypted))
+            }
+        };
+
+        return Some((status, decrypted));
+    }
+
+    status.signature = Some(signature_status(config, raw, &status.signer).await?);
+    Some((status, None))
+}
+
+/// Replaces the body of the given encrypted message with the given
+/// decrypted part. Content headers of the encrypted message are
+/// removed, since the decrypted part comes with its own.
+pub fn with_decrypted_body(raw: &[u8], decrypted: &[u8]) -> Vec<u8> {
+    // the headers end with the first empty line
+    let end = raw
+        .windows(3)
+        .position(|w| w.starts_with(b"\n\n") || w == b"\n\r\n")
+        .map_or(raw.len(), |i| i + 1);
+
+    let mut message = Vec::with_capacity(end + decrypted.len());
+    let mut skip = false;
+
+    for line in raw[..end].split_inclusive(|&b| b == b'\n') {
+        // folded lines belong to the previous header
+        if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            skip = line.len() >= 8 && line[..8].eq_ignore_ascii_case(b"content-");
+        }
+
+        if !skip {
+            message.extend_from_slice(line);
+        }
+    }
+
+    message.extend_from_slice(decrypted);
+    message
+}
+
+fn sender(message: &Message) -> Option<String> {
+    let addr = message.from()?.first()?.address()?;
+    Some(addr.to_owned())
+}
+
+/// Returns the two parts of the given multipart/signed or
+/// multipart/encrypted message.
+fn subparts<'a>(message: &'a Message) -> Option<(&'a MessagePart<'a>, &'a MessagePart<'a>)> {
+    let PartType::Multipart(ids) = &message.parts.first()?.body else {
+        return None;
+    };
+
+    let first = message.parts.get(*ids.first()?)?;
+    let second = message.parts.get(*ids.get(1)?)?;
+    Some((first, second))
+}
+
+async fn signature_status(
+    config: &AccountConfig,
+    raw: &[u8],
+    signer: &str,
+) -> Option<SignatureStatus> {
+    let message = MessageParser::new().parse(raw)?;
+
+    if !message.is_content_type("multipart", "signed") {
+        return None;
+    }
+
+    let Some((signed, signature)) = subparts(&message) else {
+        return Some(SignatureStatus::Bad);
+    };
+
+    // the signed data is the whole signed part, headers included
+    let signed_data = message
+        .raw_message()
+        .get(signed.offset_header..signed.offset_end)?;
+    let signed_data = canonicalize(signed_data);
+
+    Some(verify(config, signer, signature.contents(), signed_data).await)
+}
+
+/// Converts line endings of the given data to CRLF, as required by
+/// RFC3156 before checking signatures.
+fn canonicalize(data: &[u8]) -> Vec<u8> {
+    let mut canonical = Vec::with_capacity(data.len());
+    let mut prev = 0;
+
+    for &byte in data {
+        if byte == b'\n' && prev != b'\r' {
+            canonical.push(b'\r');
+        }
+
+        canonical.push(byte);
+        prev = byte;
+    }
+
+    canonical
+}
+
+#[cfg(feature = "pgp")]
+async fn decrypt(config: &AccountConfig, data: &[u8]) -> Option<Result<Vec<u8>, String>> {
+    let pgp = config.pgp.as_ref()?;
+
+    Some(
+        pgp.decrypt(&config.email, data.to_vec())
+            .await
+            .map_err(|err| err.to_string()),
+    )
+}
+
+#[cfg(not(feature = "pgp"))]
+async fn decrypt(_config: &AccountConfig, _data: &[u8]) -> Option<Result<Vec<u8>, String>> {
+    None
+}
+
+#[cfg(feature = "pgp")]
+async fn verify(
+    config: &AccountConfig,
+    signer: &str,
+    signature: &[u8],
+    signed_data: Vec<u8>,
+) -> SignatureStatus {
+    let Some(pgp) = config.pgp.as_ref() else {
+        return SignatureStatus::Unchecked;
+    };
+
+    match pgp.verify(signer, signature.to_vec(), signed_data).await {
+        Ok(true) => SignatureStatus::Valid,
+        Ok(false) => SignatureStatus::Bad,
+        Err(err) if is_missing_key_error(&err) => SignatureStatus::UnknownKey(err.to_string()),
+        Err(err) => SignatureStatus::Error(err.to_string()),
+    }
+}
+
+/// Tells whether the given verification error is caused by the
+/// public key of the signer not being found, by looking for the key
+/// lookup error of the PGP backend among its sources.
+#[cfg(feature = "pgp")]
+fn is_missing_key_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    use email::account::config::pgp::Error;
+
+    std::iter::successors(Some(err), |err| err.source()).any(|err| {
+        matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::FindPgpPublicKeyError(_))
+        )
+    })
+}
+
+#[cfg(not(feature = "pgp"))]
+async fn verify(
+    _config: &AccountConfig,
+    _signer: &str,
+    _signature: &[u8],
+    _signed_data: Vec<u8>,
+) -> SignatureStatus {
+    SignatureStatus::Unchecked
+}
+
+/// Splits the given template into its headers, including the empty
+/// line, and its body.
+fn split_tpl(tpl: &str) -> (&str, &str) {
+    match tpl.find("\n\n") {
+        Some(pos) => tpl.split_at(pos + 2),
+        None => (tpl, ""),
+    }
+}
+
+/// Detects the MML wrapper added by [`wrap_tpl`], and returns its
+/// opening tag with the wrapped body.
+fn unwrap_body(body: &str) -> Option<(&str, &str)> {
+    let (tag, inner) = body.split_once('\n')?;
+
+    if !tag.starts_with("<#multipart") || !(tag.contains(SIGN_PROP) || tag.contains(ENCRYPT_PROP)) {
+        return None;
+    }
+
+    let inner = inner.trim_end().strip_suffix(CLOSING_TAG)?;
+    Some((tag, inner.strip_suffix('\n').unwrap_or(inner)))
+}
+
+/// Returns whether the body of the given template is wrapped to be
+/// signed and encrypted, respectively.
+pub fn tpl_security(tpl: &str) -> (bool, bool) {
+    let (_, body) = split_tpl(tpl);
+
+    match unwrap_body(body) {
+        Some((tag, _)) => (tag.contains(SIGN_PROP), tag.contains(ENCRYPT_PROP)),
+        None => (false, false),
+    }
+}
+
+/// Wraps the body of the given template in a MML multipart signing
+/// and/or encrypting it. A previous wrapper is replaced, and removed
+/// when neither signing nor encryption is wanted.
+pub fn wrap_tpl(tpl: &str, sign: bool, encrypt: bool) -> String {
+    let (headers, body) = split_tpl(tpl);
+    let body = unwrap_body(body).map(|(_, inner)| inner).unwrap_or(body);
+
+    if !sign && !encrypt {
+        return format!("{headers}{body}");
+    }
+
+    let mut tag = String::from("<#multipart type=mixed");
+
+    if sign {
+        tag.push(' ');
+        tag.push_str(SIGN_PROP);
+    }
+
+    if encrypt {
+        tag.push(' ');
+        tag.push_str(ENCRYPT_PROP);
+    }
+
+    format!("{headers}{tag}>\n{body}\n{CLOSING_TAG}\n")
+}
+
+/// Returns `true` if the body of the given template is wrapped by
+/// [`wrap_tpl`] to be encrypted. Only the opening tag of the wrapper
+/// is considered, so that quoted text mentioning encryption is not
+/// taken for it.
+pub fn is_encrypted_tpl(tpl: &str) -> bool {
+    tpl_security(tpl).1
+}
+
+/// Lists the recipients of the given template.
+#[cfg(feature = "pgp")]
+fn tpl_recipients(tpl: &str) -> Vec<String> {
+    let Some(message) = MessageParser::new().parse(tpl.as_bytes()) else {
+        return Vec::new();
+    };
+
+    [message.to(), message.cc(), message.bcc()]
+        .into_iter()
+        .flatten()
+        .flat_map(|addrs| addrs.iter())
+        .filter_map(|addr| addr.address())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Lists the recipients of the given template whose public key cannot
+/// be found by the PGP backend of the account.
+#[cfg(feature = "pgp")]
+pub async fn missing_keys(config: &AccountConfig, tpl: &str) -> Result<Vec<String>> {
+    let pgp = config
+        .pgp
+        .as_ref()
+        .ok_or(eyre!("cannot encrypt message: PGP is not configured"))?;
+
+    let mut missing = Vec::new();
+
+    for recipient in tpl_recipients(tpl) {
+        // encrypting an empty payload is the only way to check that
+        // a key is available, whatever the PGP backend
+        if pgp.encrypt([recipient.clone()], Vec::new()).await.is_err() {
+            missing.push(recipient);
+        }
+    }
+
+    Ok(missing)
+}
+
+#[cfg(not(feature = "pgp"))]
+pub async fn missing_keys(_config: &AccountConfig, _tpl: &str) -> Result<Vec<String>> {
+    Err(eyre!("cannot encrypt message: the pgp feature is disabled"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_encrypted_tpl, with_decrypted_body, wrap_tpl};
+
+    #[test]
+    fn encrypted_tpl() {
+        let tpl = "To: bob@localhost\n\n> <#part encrypt=pgpmime>\n";
+        assert!(!is_encrypted_tpl(tpl));
+        assert!(is_encrypted_tpl(&wrap_tpl(tpl, false, true)));
+        assert!(!is_encrypted_tpl(&wrap_tpl(tpl, true, false)));
+    }
+
+    #[test]
+    fn decrypted_body() {
+        let raw = b"From: alice@localhost\r\n\
+                    Content-Type: multipart/encrypted;\r\n\
+                    \tprotocol=\"application/pgp-encrypted\"\r\n\
+                    Subject: Secret\r\n\
+                    \r\n\
+                    -----BEGIN PGP MESSAGE-----\r\n";
+        let decrypted = b"Content-Type: text/plain\r\n\r\nHello\r\n";
+
+        assert_eq!(
+            with_decrypted_body(raw, decrypted),
+            b"From: alice@localhost\r\n\
+              Subject: Secret\r\n\
+              Content-Type: text/plain\r\n\
+              \r\n\
+              Hello\r\n"
+        );
+    }
+}
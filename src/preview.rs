@@ -0,0 +1,135 @@
+//! Message preview module.
+//!
+//! This module renders the MIME structure of compiled messages, so
+//! users can check what is about to be sent.
+
+use std::fmt::Write;
+
+use color_eyre::{eyre::eyre, Result};
+use mail_parser::{Message, MessageParser, MessagePart, MimeHeaders, PartType};
+
+/// Formats the given size in bytes using binary units.
+pub fn format_size(size: usize) -> String {
+    match size {
+        size if size < 1024 => format!("{size} B"),
+        size if size < 1024 * 1024 => format!("{:.1} KiB", size as f64 / 1024.0),
+        size => format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// Renders the main headers and the MIME structure tree of the given
+/// raw message.
+pub fn mime_tree(raw: &[u8]) -> Result<String> {
+    let message = MessageParser::new()
+        .parse(raw)
+        .ok_or(eyre!("cannot parse compiled message"))?;
+
+    let mut tree = String::new();
+
+    for (name, addrs) in [
+        ("From", message.from()),
+        ("To", message.to()),
+        ("Cc", message.cc()),
+        ("Bcc", message.bcc()),
+    ] {
+        let Some(addrs) = addrs else {
+            continue;
+        };
+
+        let addrs = addrs
+            .iter()
+            .filter_map(|addr| addr.address())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        writeln!(tree, "{name}: {addrs}")?;
+    }
+
+    writeln!(tree, "Subject: {}", message.subject().unwrap_or_default())?;
+    writeln!(tree, "Size: {}", format_size(raw.len()))?;
+    writeln!(tree)?;
+
+    write_part(&mut tree, &message, 0, "", "")?;
+
+    Ok(tree)
+}
+
+/// Describes the content type of the given part, with its file name
+/// if any.
+fn describe_part(part: &MessagePart) -> String {
+    let mut desc = match part.content_type() {
+        Some(ctype) => match ctype.subtype() {
+            Some(subtype) => format!("{}/{subtype}", ctype.ctype()),
+            None => ctype.ctype().to_owned(),
+        },
+        None => String::from("text/plain"),
+    };
+
+    if let Some(name) = part.attachment_name() {
+        desc.push_str(&format!(" \"{name}\""));
+    }
+
+    if part.is_content_type("multipart", "signed") {
+        desc.push_str(" [signed]");
+    }
+
+    if part.is_content_type("multipart", "encrypted")
+        || part.is_content_type("application", "pgp-encrypted")
+    {
+        desc.push_str(" [encrypted]");
+    }
+
+    desc
+}
+
+fn write_part(
+    tree: &mut String,
+    message: &Message,
+    id: usize,
+    prefix: &str,
+    child_prefix: &str,
+) -> Result<()> {
+    let Some(part) = message.parts.get(id) else {
+        return Ok(());
+    };
+
+    let size = part.offset_end.saturating_sub(part.offset_header);
+    writeln!(
+        tree,
+        "{prefix}{} ({})",
+        describe_part(part),
+        format_size(size)
+    )?;
+
+    match &part.body {
+        PartType::Multipart(ids) => {
+            for (i, id) in ids.iter().enumerate() {
+                let (prefix, next_prefix) = if i + 1 == ids.len() {
+                    ("└─ ", "   ")
+                } else {
+                    ("├─ ", "│  ")
+                };
+
+                write_part(
+                    tree,
+                    message,
+                    *id,
+                    &format!("{child_prefix}{prefix}"),
+                    &format!("{child_prefix}{next_prefix}"),
+                )?;
+            }
+        }
+        PartType::Message(nested) => {
+            write_part(
+                tree,
+                nested,
+                0,
+                &format!("{child_prefix}└─ "),
+                &format!("{child_prefix}   "),
+            )?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
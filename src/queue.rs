@@ -0,0 +1,349 @@
+//! Send queue module.
+//!
+//! Messages sent with a delay are held in this queue until the delay
+//! expires, which leaves a chance to undo the sending. Their local
+//! draft is kept until they are actually sent.
+//!
+//! Messages which cannot be sent, as well as scheduled ones, are
+//! stored in the persistent [`Outbox`], which is flushed periodically
+//! while the REPL is running.
+
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use color_eyre::Result;
+use email::{
+    flag::Flag,
+    folder::SENT,
+    message::{add::AddMessage, send::SendMessage},
+};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::{
+    backend::Backend,
+    draft::LocalDraft,
+    editor::remove_remote_draft,
+    outbox::{Outbox, OutboxMessage},
+};
+
+/// A compiled message waiting to be sent.
+pub struct PendingMessage {
+    pub draft: LocalDraft,
+    pub subject: String,
+    pub send_at: Instant,
+    email: Vec<u8>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct SendQueueState {
+    pending: Vec<PendingMessage>,
+    /// Tasks of the messages whose delay expired, kept so that they
+    /// can be awaited before exiting.
+    sending: Vec<JoinHandle<()>>,
+    reports: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct SendQueue {
+    /// Swapped when the session goes back online, so that messages
+    /// are no longer sent through the offline backend.
+    backend: Arc<RwLock<Arc<Backend>>>,
+    outbox: Outbox,
+    delay: Duration,
+    state: Arc<Mutex<SendQueueState>>,
+    /// Prevents the outbox from being flushed twice at the same time,
+    /// which would send messages twice.
+    flushing: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SendQueue {
+    pub fn new(backend: Arc<Backend>, outbox: Outbox, delay: Duration) -> Self {
+        Self {
+            backend: Arc::new(RwLock::new(backend)),
+            outbox,
+            delay,
+            state: Default::default(),
+            flushing: Default::default(),
+        }
+    }
+
+    /// Replaces the backend used to send messages and to remove their
+    /// remote drafts, including by the tasks already spawned.
+    pub fn set_backend(&self, backend: Arc<Backend>) {
+        *self.backend.write().unwrap() = backend;
+    }
+
+    fn backend(&self) -> Arc<Backend> {
+        self.backend.read().unwrap().clone()
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    pub fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    fn report(&self, report: impl ToString) {
+        self.state.lock().unwrap().reports.push(report.to_string());
+    }
+
+    /// Sends the given compiled message, then removes its drafts. If
+    /// the sending fails, the message is moved to the outbox so that
+    /// it can be sent later on, and its drafts are removed as well.
+    ///
+    /// Returns a report intended for the user.
+    pub async fn send(&self, draft: &LocalDraft, email: &[u8]) -> Result<String> {
+        let subject = draft.subject().unwrap_or_default();
+        debug!("sending message {subject}");
+
+        let report = match self.backend().send_message(email).await {
+            Ok(()) => match self.save_sent_copy(email).await {
+                Ok(()) => format!("Message \"{subject}\" successfully sent"),
+                Err(err) => format!(
+                    "Message \"{subject}\" successfully sent, but its copy cannot be saved: {err}"
+                ),
+            },
+            Err(err) => {
+                let mut message = self.outbox.push(&subject, email, None)?;
+                message.record_failure(&err)?;
+                format!(
+                    "Cannot send message \"{subject}\", it has been moved to the outbox as {}: {err}",
+                    message.id
+                )
+            }
+        };
+
+        self.discard_remote_draft(draft).await;
+        draft.remove()?;
+        Ok(report)
+    }
+
+    /// Holds the given compiled message in the queue, and sends it
+    /// once the delay expires.
+    pub fn push(&self, draft: LocalDraft, email: Vec<u8>) {
+        let id = draft.id.clone();
+        let queue = self.clone();
+
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(queue.delay).await;
+
+            if let Some((draft, email)) = queue.take(&id) {
+                queue.send_pending(&draft, &email).await;
+            }
+        });
+
+        let pending = PendingMessage {
+            subject: draft.subject().unwrap_or_default(),
+            draft,
+            send_at: Instant::now() + self.delay,
+            email,
+            task,
+        };
+
+        self.state.lock().unwrap().pending.push(pending);
+    }
+
+    /// Stores the given compiled message in the outbox, to be sent at
+    /// the given date. The drafts of the message are removed.
+    pub async fn schedule(
+        &self,
+        draft: &LocalDraft,
+        email: &[u8],
+        send_at: SystemTime,
+    ) -> Result<OutboxMessage> {
+        let subject = draft.subject().unwrap_or_default();
+        let message = self.outbox.push(subject, email, Some(send_at))?;
+        self.discard_remote_draft(draft).await;
+        draft.remove()?;
+        Ok(message)
+    }
+
+    /// Saves a copy of the given sent message to the sent folder, if
+    /// enabled by the account configuration.
+    ///
+    /// This is kept apart from the sending, so that a message which
+    /// has been delivered is never moved to the outbox.
+    async fn save_sent_copy(&self, email: &[u8]) -> Result<()> {
+        let backend = self.backend();
+
+        if backend.account_config.should_save_copy_sent_message() {
+            backend
+                .add_message_with_flag(SENT, email, Flag::Seen)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the copy of the given draft from the drafts folder.
+    ///
+    /// Failures are only logged: the message is either sent or stored
+    /// in the outbox at this point, so its local draft must go anyway.
+    async fn discard_remote_draft(&self, draft: &LocalDraft) {
+        if let Err(err) = remove_remote_draft(&self.backend(), draft).await {
+            warn!("cannot remove remote draft of {}: {err}", draft.id);
+        }
+    }
+
+    /// Removes the given message from the pending ones. Its task is
+    /// kept until the message is sent.
+    fn take(&self, id: &str) -> Option<(LocalDraft, Vec<u8>)> {
+        let mut state = self.state.lock().unwrap();
+        let pos = state.pending.iter().position(|p| p.draft.id == id)?;
+        let pending = state.pending.remove(pos);
+
+        state.sending.retain(|task| !task.is_finished());
+        state.sending.push(pending.task);
+
+        Some((pending.draft, pending.email))
+    }
+
+    async fn send_pending(&self, draft: &LocalDraft, email: &[u8]) {
+        match self.send(draft, email).await {
+            Ok(report) => self.report(report),
+            Err(err) => self.report(format!(
+                "Cannot send message \"{}\": {err}",
+                draft.subject().unwrap_or_default()
+            )),
+        }
+    }
+
+    /// Cancels the sending of the most recently queued message.
+    ///
+    /// The local draft of the message is left untouched.
+    pub fn undo(&self) -> Option<PendingMessage> {
+        let pending = self.state.lock().unwrap().pending.pop()?;
+        pending.task.abort();
+        Some(pending)
+    }
+
+    /// Sends all pending messages right away, without waiting for the
+    /// delay to expire, then waits for the messages being sent.
+    pub async fn flush(&self) {
+        let (pending, sending) = {
+            let mut state = self.state.lock().unwrap();
+            let pending = std::mem::take(&mut state.pending);
+            let sending = std::mem::take(&mut state.sending);
+            (pending, sending)
+        };
+
+        for pending in pending {
+            pending.task.abort();
+            self.send_pending(&pending.draft, &pending.email).await;
+        }
+
+        for task in sending {
+            if let Err(err) = task.await {
+                debug!("cannot wait for message to be sent: {err}");
+            }
+        }
+    }
+
+    /// Sends outbox messages which are due, or all of them when
+    /// `force` is `true`.
+    ///
+    /// Failures are only reported when `force` is `true`, otherwise
+    /// they are recorded in the outbox and retried at the next flush.
+    pub async fn flush_outbox(&self, force: bool) -> Result<()> {
+        let _guard = self.flushing.lock().await;
+
+        for message in self.outbox.list()? {
+            if force || message.is_due() {
+                // one broken message must not hold back the other ones
+                let id = message.id.clone();
+                if let Err(err) = self.send_outbox_message(message, force).await {
+                    warn!("cannot send outbox message {id}: {err}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the given outbox message right away, whatever its
+    /// schedule.
+    ///
+    /// Returns `false` if the outbox has no such message.
+    pub async fn retry(&self, id: &str) -> Result<bool> {
+        let _guard = self.flushing.lock().await;
+
+        let Some(message) = self.outbox.get(id)? else {
+            return Ok(false);
+        };
+
+        self.send_outbox_message(message, true).await?;
+        Ok(true)
+    }
+
+    async fn send_outbox_message(&self, mut message: OutboxMessage, verbose: bool) -> Result<()> {
+        let subject = message.subject.clone();
+        let email = message.read()?;
+
+        match self.backend().send_message(&email).await {
+            Ok(()) => {
+                message.remove()?;
+
+                match self.save_sent_copy(&email).await {
+                    Ok(()) => self.report(format!(
+                        "Message \"{subject}\" successfully sent from the outbox"
+                    )),
+                    Err(err) => self.report(format!(
+                        "Message \"{subject}\" successfully sent from the outbox, but its copy cannot be saved: {err}"
+                    )),
+                }
+            }
+            Err(err) => {
+                debug!("cannot send outbox message {}: {err}", message.id);
+                message.record_failure(&err)?;
+
+                if verbose {
+                    self.report(format!(
+                        "Cannot send message \"{subject}\" from the outbox: {err}"
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Spawns a task flushing the outbox at the given interval.
+    pub fn spawn_outbox_flusher(&self, interval: Duration) -> JoinHandle<()> {
+        let queue = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = queue.flush_outbox(false).await {
+                    debug!("cannot flush outbox: {err}");
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+
+    /// Lists sending reports since the last call, followed by the
+    /// countdown of pending messages.
+    pub fn status(&self) -> Vec<String> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        let mut status = std::mem::take(&mut state.reports);
+
+        for pending in &state.pending {
+            let secs = pending.send_at.saturating_duration_since(now).as_secs();
+            status.push(format!(
+                "Sending \"{}\" in {secs}s, type `undo` to cancel",
+                pending.subject
+            ));
+        }
+
+        status
+    }
+}
@@ -0,0 +1,442 @@
+//! Read view module.
+//!
+//! This module renders read templates in the terminal: headers are
+//! coloured, quoted lines are dimmed by depth, URLs and signatures
+//! are highlighted, and the body is wrapped according to the
+//! `message.read.format` option.
+
+use std::{borrow::Cow, ops::Range};
+
+use crossterm::style::{Attribute, Color};
+use email::email::config::EmailTextPlainFormat;
+use unicode_width::UnicodeWidthStr;
+
+use crate::theme::Theme;
+
+static DEFAULT_SIGNATURE_DELIM: &str = "-- \n";
+
+static URL_SCHEMES: [&str; 3] = ["https://", "http://", "mailto:"];
+
+/// Renders read templates with the colours of a theme.
+pub struct Reader {
+    theme: Theme,
+    format: EmailTextPlainFormat,
+    width: Option<u16>,
+    signature_delim: Option<String>,
+}
+
+impl Reader {
+    pub fn new(theme: Theme) -> Self {
+        Self {
+            theme,
+            format: EmailTextPlainFormat::default(),
+            width: None,
+            signature_delim: None,
+        }
+    }
+
+    pub fn with_format(mut self, format: EmailTextPlainFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Wraps the body to the given width, unless the format is fixed.
+    pub fn with_some_width(mut self, width: Option<u16>) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Sets the line from which the body is considered as a
+    /// signature. Defaults to `-- `.
+    pub fn with_some_signature_delim(mut self, delim: Option<String>) -> Self {
+        self.signature_delim = delim;
+        self
+    }
+
+    fn wrap_width(&self) -> Option<usize> {
+        match &self.format {
+            EmailTextPlainFormat::Fixed(width) => Some(*width),
+            EmailTextPlainFormat::Auto | EmailTextPlainFormat::Flowed => {
+                self.width.map(usize::from)
+            }
+        }
+    }
+
+    fn signature_delim(&self) -> &str {
+        self.signature_delim
+            .as_deref()
+            .unwrap_or(DEFAULT_SIGNATURE_DELIM)
+            .trim_end_matches(['\r', '\n'])
+    }
+
+    /// Renders the given read template, made of headers followed by
+    /// an empty line and the body.
+    pub fn render(&self, tpl: &str) -> String {
+        let mut rendered = String::with_capacity(tpl.len());
+        let mut lines = tpl.lines();
+
+        for line in lines.by_ref() {
+            if line.is_empty() {
+                rendered.push('\n');
+                break;
+            }
+
+            rendered.push_str(&self.render_header(line));
+            rendered.push('\n');
+        }
+
+        let body: Vec<&str> = lines.collect();
+
+        for line in self.render_body(&body) {
+            rendered.push_str(&line);
+            rendered.push('\n');
+        }
+
+        if !tpl.ends_with('\n') {
+            rendered.pop();
+        }
+
+        rendered
+    }
+
+    fn render_header(&self, line: &str) -> String {
+        let theme = &self.theme;
+
+        match line.split_once(':') {
+            Some((name, value)) if !line.starts_with(char::is_whitespace) => {
+                let name = theme
+                    .paint(&format!("{name}:"), theme.header_name)
+                    .into_owned();
+                let value = theme.paint(value, theme.header_value);
+                format!("{name}{value}")
+            }
+            _ => theme.paint(line, theme.header_value).into_owned(),
+        }
+    }
+
+    fn render_body(&self, lines: &[&str]) -> Vec<String> {
+        let delim = self.signature_delim();
+
+        let lines: Vec<Cow<str>> = match &self.format {
+            EmailTextPlainFormat::Flowed => {
+                unflow(lines, delim).into_iter().map(Cow::Owned).collect()
+            }
+            EmailTextPlainFormat::Auto | EmailTextPlainFormat::Fixed(_) => {
+                lines.iter().copied().map(Cow::Borrowed).collect()
+            }
+        };
+
+        let mut rendered = Vec::with_capacity(lines.len());
+        let mut signature = false;
+
+        for line in lines {
+            signature |= line == delim;
+
+            let (prefix, depth) = quote_prefix(&line);
+            let content = &line[prefix.len()..];
+            let width = self
+                .wrap_width()
+                .map(|width| width.saturating_sub(prefix.width()));
+
+            for wrapped in wrap(content, width) {
+                let line = format!("{prefix}{wrapped}");
+                rendered.push(self.render_line(&line, depth, signature));
+            }
+        }
+
+        rendered
+    }
+
+    fn render_line(&self, line: &str, depth: usize, signature: bool) -> String {
+        let theme = &self.theme;
+
+        let style = if signature {
+            Some((theme.signature, Attribute::Dim))
+        } else if depth > 0 {
+            Some((self.quote_color(depth), Attribute::Dim))
+        } else {
+            None
+        };
+
+        let mut rendered = String::with_capacity(line.len());
+        let mut last = 0;
+
+        for url in find_urls(line) {
+            rendered.push_str(&self.paint(&line[last..url.start], style));
+            rendered.push_str(&theme.style(&line[url.clone()], theme.url, Attribute::Underlined));
+            last = url.end;
+        }
+
+        rendered.push_str(&self.paint(&line[last..], style));
+        rendered
+    }
+
+    fn paint<'a>(&self, text: &'a str, style: Option<(Option<Color>, Attribute)>) -> Cow<'a, str> {
+        match style {
+            Some((color, attribute)) => self.theme.style(text, color, attribute),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// Returns the colour of quoted lines of the given depth, cycling
+    /// through the colours of the theme.
+    fn quote_color(&self, depth: usize) -> Option<Color> {
+        let quotes = &self.theme.quotes;
+        quotes
+            .get(depth.saturating_sub(1) % quotes.len().max(1))
+            .copied()
+    }
+}
+
+/// Splits the quote markers of the given line from its content, and
+/// returns them along with the quote depth.
+fn quote_prefix(line: &str) -> (&str, usize) {
+    let mut depth = 0;
+    let mut end = 0;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '>' => {
+                depth += 1;
+                end = i + 1;
+            }
+            ' ' if depth > 0 => end = i + 1,
+            _ => break,
+        }
+    }
+
+    (&line[..end], depth)
+}
+
+/// Joins the lines ending with a space to the following ones of the
+/// same quote depth, as defined in RFC3676.
+fn unflow(lines: &[&str], delim: &str) -> Vec<String> {
+    let mut unflowed: Vec<String> = Vec::with_capacity(lines.len());
+    let mut flowing = false;
+    let mut last_depth = 0;
+
+    for line in lines {
+        let (prefix, depth) = quote_prefix(line);
+
+        match unflowed.last_mut() {
+            Some(last) if flowing && depth == last_depth => last.push_str(&line[prefix.len()..]),
+            _ if depth == 0 => unflowed.push(unstuff(line).to_owned()),
+            _ => unflowed.push(line.to_string()),
+        }
+
+        flowing = line.ends_with(' ') && *line != delim;
+        last_depth = depth;
+    }
+
+    unflowed
+}
+
+/// Removes the space stuffing the given unquoted line, unless it
+/// prevents the line from being taken for a quoted one.
+fn unstuff(line: &str) -> &str {
+    match line.strip_prefix(' ') {
+        Some(unstuffed) if !unstuffed.starts_with('>') => unstuffed,
+        _ => line,
+    }
+}
+
+/// Wraps the given text on spaces so that lines fit in the given
+/// width. Words longer than the width are kept whole, so that URLs
+/// are not broken. The leading whitespace of the text is repeated at
+/// the beginning of each line, like quote markers.
+fn wrap(text: &str, width: Option<usize>) -> Vec<Cow<'_, str>> {
+    match width {
+        Some(width) if width > 0 && text.width() > width => {
+            let content = text.trim_start_matches([' ', '\t']);
+            let indent = &text[..text.len() - content.len()];
+            let width = width.saturating_sub(indent.width());
+
+            let mut lines = Vec::new();
+            let mut line = String::new();
+
+            for word in content.split(' ') {
+                if !line.is_empty() && line.width() + 1 + word.width() > width {
+                    lines.push(Cow::Owned(format!("{indent}{line}")));
+                    line.clear();
+                } else if !line.is_empty() {
+                    line.push(' ');
+                }
+
+                line.push_str(word);
+            }
+
+            lines.push(Cow::Owned(format!("{indent}{line}")));
+            lines
+        }
+        _ => vec![Cow::Borrowed(text)],
+    }
+}
+
+/// Finds the URLs of the given line, without their trailing
+/// punctuation.
+fn find_urls(line: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut start = 0;
+
+    while let Some(i) = URL_SCHEMES
+        .iter()
+        .filter_map(|scheme| line[start..].find(scheme))
+        .min()
+        .map(|i| start + i)
+    {
+        let end = line[i..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+            .map_or(line.len(), |j| i + j);
+
+        let url = line[i..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'']);
+        urls.push(i..i + url.len());
+        start = end;
+    }
+
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::style::Color;
+    use email::email::config::EmailTextPlainFormat as ReadFormat;
+
+    use super::{find_urls, Reader};
+    use crate::theme::{Theme, ThemeKind};
+
+    fn reader(format: ReadFormat, width: Option<u16>) -> Reader {
+        let theme = Theme::new(ThemeKind::Dark).with_styling(false);
+        Reader::new(theme)
+            .with_format(format)
+            .with_some_width(width)
+    }
+
+    #[test]
+    fn unstyled() {
+        let tpl = "From: alice@localhost\nSubject: Hello\n\nHello Bob!\n> Hi\n";
+        assert_eq!(reader(ReadFormat::Auto, None).render(tpl), tpl);
+    }
+
+    #[test]
+    fn wrap_auto() {
+        let tpl = "Subject: Fox\n\nThe quick brown fox jumps over the lazy dog\nShort line\n";
+
+        let expected = "Subject: Fox\n\n\
+                        The quick brown fox\n\
+                        jumps over the lazy\n\
+                        dog\n\
+                        Short line\n";
+
+        assert_eq!(reader(ReadFormat::Auto, Some(20)).render(tpl), expected);
+    }
+
+    #[test]
+    fn wrap_quotes() {
+        let tpl = "Subject: Re: Fox\n\n> > aaa bbb ccc\n";
+        let expected = "Subject: Re: Fox\n\n> > aaa\n> > bbb\n> > ccc\n";
+        assert_eq!(reader(ReadFormat::Auto, Some(10)).render(tpl), expected);
+    }
+
+    #[test]
+    fn wrap_indented() {
+        let tpl = "Subject: Code\n\n    aaa bbb ccc\n";
+        let expected = "Subject: Code\n\n    aaa\n    bbb\n    ccc\n";
+        assert_eq!(reader(ReadFormat::Auto, Some(10)).render(tpl), expected);
+    }
+
+    #[test]
+    fn wrap_fixed() {
+        let tpl = "Subject: Fox\n\nThe quick brown fox\n";
+        let expected = "Subject: Fox\n\nThe quick\nbrown fox\n";
+
+        // the fixed width takes precedence over the terminal one
+        assert_eq!(
+            reader(ReadFormat::Fixed(10), Some(80)).render(tpl),
+            expected
+        );
+        assert_eq!(reader(ReadFormat::Fixed(10), None).render(tpl), expected);
+    }
+
+    #[test]
+    fn flowed() {
+        let tpl = "Subject: Flowed\n\n\
+                   Hello \n\
+                   world\n \
+                   >stuffed\n \
+                   From me\n\
+                   > quoted \n\
+                   > text\n\
+                   -- \n\
+                   Alice\n";
+
+        let expected = "Subject: Flowed\n\n\
+                        Hello world\n \
+                        >stuffed\n\
+                        From me\n\
+                        > quoted text\n\
+                        -- \n\
+                        Alice\n";
+
+        assert_eq!(reader(ReadFormat::Flowed, None).render(tpl), expected);
+    }
+
+    #[test]
+    fn styles() {
+        let theme = Theme::new(ThemeKind::Dark).with_styling(true);
+        let reader = Reader::new(theme);
+
+        let rendered = reader.render(
+            "Subject: Hello\n\n\
+             See https://example.com.\n\
+             > one\n\
+             > > two\n\
+             -- \n\
+             Alice\n",
+        );
+
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        // blue header names
+        assert!(lines[0].starts_with("\u{1b}[38;5;12mSubject:"));
+
+        // underlined URLs, without trailing punctuation
+        assert!(lines[2].starts_with("See "));
+        assert!(lines[2].contains("\u{1b}[4mhttps://example.com\u{1b}[0m."));
+
+        // dimmed quotes, coloured by depth
+        assert_eq!(reader.quote_color(1), Some(Color::Cyan));
+        assert_eq!(reader.quote_color(2), Some(Color::Magenta));
+        assert_eq!(reader.quote_color(4), Some(Color::Cyan));
+        assert!(lines[3].contains("\u{1b}[38;5;14m") && lines[3].contains("\u{1b}[2m"));
+        assert!(lines[4].contains("\u{1b}[38;5;13m") && lines[4].contains("\u{1b}[2m"));
+
+        // dimmed signature
+        assert!(lines[5].contains("\u{1b}[38;5;8m"));
+        assert!(lines[6].contains("\u{1b}[38;5;8m"));
+    }
+
+    #[test]
+    fn custom_signature_delim() {
+        let theme = Theme::new(ThemeKind::Dark).with_styling(true);
+        let reader = Reader::new(theme).with_some_signature_delim(Some(String::from("~~\n")));
+
+        let rendered = reader.render("Subject: Hello\n\nBody\n-- \n~~\nAlice\n");
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[2], "Body");
+        assert_eq!(lines[3], "-- ");
+        assert!(lines[4].contains("\u{1b}[38;5;8m"));
+    }
+
+    #[test]
+    fn urls() {
+        let line = "Links: <https://a.org/x>, (http://b.org), mailto:bob@localhost.";
+
+        let urls: Vec<&str> = find_urls(line).into_iter().map(|url| &line[url]).collect();
+
+        assert_eq!(
+            urls,
+            ["https://a.org/x", "http://b.org", "mailto:bob@localhost"]
+        );
+    }
+}
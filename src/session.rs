@@ -0,0 +1,1412 @@
+//! Session module.
+//!
+//! A session holds the state of the REPL for one account, and
+//! executes the command lines read from its [`Input`]. Outputs are
+//! written to the given writers, which allows sessions to be driven
+//! by tests.
+
+use std::{io::Write, sync::Arc};
+
+use color_eyre::{eyre::eyre, Result};
+use crossterm::terminal;
+#[cfg(feature = "imap")]
+use email::imap::ImapContextBuilder;
+#[cfg(feature = "maildir")]
+use email::maildir::MaildirContextBuilder;
+#[cfg(feature = "notmuch")]
+use email::notmuch::NotmuchContextBuilder;
+#[cfg(feature = "sendmail")]
+use email::sendmail::SendmailContextBuilder;
+#[cfg(feature = "smtp")]
+use email::smtp::SmtpContextBuilder;
+use email::{
+    account::config::AccountConfig,
+    backend::BackendBuilder,
+    envelope::{
+        list::{ListEnvelopes, ListEnvelopesOptions},
+        Id,
+    },
+    flag::{add::AddFlags, Flag},
+    folder::{list::ListFolders, DRAFTS, SENT},
+    message::{
+        copy::CopyMessages, delete::DeleteMessages, get::GetMessages, peek::PeekMessages,
+        r#move::MoveMessages, Message,
+    },
+    template::forward::config::ForwardTemplatePostingStyle,
+};
+use pimalaya_tui::config::toml::TomlConfig as _;
+use reedline::{DefaultPrompt, DefaultPromptSegment};
+#[cfg(feature = "sync")]
+use tokio::sync::mpsc;
+
+#[cfg(feature = "memory")]
+use crate::memory::MemoryContextBuilder;
+use crate::{
+    account::config::TomlAccountConfig,
+    backend::{self, Backend, BackendKind, ContextBuilder},
+    compose,
+    config::{KeybindsStyle, ReplConfig, TomlConfig},
+    contact::{AddressBook, ContactsTable},
+    draft::{DraftOrigin, LocalDrafts, LocalDraftsTable},
+    editor,
+    envelope::{ColumnKind, Envelopes, EnvelopesTable},
+    folder::FoldersTable,
+    id_mapper::IdMapper,
+    identity::{Identities, IdentitiesTable},
+    input::{self, Input, Io},
+    message,
+    outbox::{self, Outbox, OutboxTable},
+    pgp,
+    queue::SendQueue,
+    reader::Reader,
+    templates::{NamedTemplates, TemplateVars},
+    theme::{Theme, ThemedPrompt},
+};
+#[cfg(feature = "sync")]
+use crate::{
+    envelope,
+    offline::{self, Journal, JournalEntry, JournalOp, JournalTable},
+    sync::Synchronizer,
+};
+
+pub static COMMANDS: &[&str] = &[
+    "help",
+    "folders",
+    "select",
+    "unselect",
+    "list",
+    "read",
+    "peek",
+    "write",
+    "compose",
+    "reply",
+    "forward",
+    "bounce",
+    "copy",
+    "move",
+    "delete",
+    "drafts",
+    "edit-draft",
+    "contacts",
+    "identity",
+    "templates",
+    "send",
+    #[cfg(feature = "sync")]
+    "sync",
+    #[cfg(feature = "sync")]
+    "online",
+    #[cfg(feature = "sync")]
+    "journal",
+    "undo",
+    "outbox",
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct SessionOptions {
+    /// The name of the account, defaults to the default account.
+    pub account: Option<String>,
+    /// Start in offline mode.
+    pub offline: bool,
+}
+
+pub struct Session {
+    account_cfg: Arc<AccountConfig>,
+    toml_account_cfg: TomlAccountConfig,
+    repl_cfg: ReplConfig,
+    keybinds: KeybindsStyle,
+    theme: Theme,
+    mark_seen: bool,
+    ctx: ContextBuilder,
+    backend: Arc<Backend>,
+    queue: SendQueue,
+    folder: Option<String>,
+    address_book: AddressBook,
+    templates: NamedTemplates,
+    identities: Identities,
+
+    #[cfg(feature = "sync")]
+    synchronizer: Option<Synchronizer>,
+    #[cfg(feature = "sync")]
+    journal: Journal,
+    #[cfg(feature = "sync")]
+    offline: bool,
+
+    input: Box<dyn Input>,
+    out: Box<dyn Write>,
+    err: Box<dyn Write>,
+}
+
+impl Session {
+    /// Starts up the backends of the account, then creates a session
+    /// reading from the given input and writing to the given outputs.
+    pub async fn new(
+        toml_cfg: TomlConfig,
+        opts: SessionOptions,
+        input: Box<dyn Input>,
+        mut out: Box<dyn Write>,
+        #[cfg_attr(not(feature = "sync"), allow(unused_mut))] mut err: Box<dyn Write>,
+    ) -> Result<Self> {
+        let keybinds = toml_cfg.repl_keybinds().cloned().unwrap_or_default();
+        let mark_seen = toml_cfg.repl_read_mark_seen();
+        let repl_cfg = toml_cfg.repl.clone().unwrap_or_default();
+        let (toml_account_cfg, account_cfg) =
+            toml_cfg.into_account_configs(opts.account.as_deref())?;
+
+        let account_cfg = Arc::new(account_cfg);
+        let theme = theme(&repl_cfg, &toml_account_cfg);
+
+        writeln!(out, "Welcome to Himalaya REPL!")?;
+        writeln!(out, "Starting up backends…")?;
+
+        let ctx = context_builder(&account_cfg, &toml_account_cfg);
+        ctx.validate()?;
+
+        #[cfg(feature = "sync")]
+        let synchronizer = match toml_account_cfg.imap {
+            Some(_) => Some(Synchronizer::new(
+                account_cfg.clone(),
+                &toml_account_cfg,
+                &repl_cfg,
+            )?),
+            None => None,
+        };
+
+        #[cfg(feature = "sync")]
+        let journal = Journal::new(&account_cfg)?;
+
+        #[cfg(feature = "sync")]
+        let (backend, offline) = match &synchronizer {
+            Some(sync) if opts.offline => {
+                let backend =
+                    offline::build_offline_backend(account_cfg.clone(), ctx.clone(), sync);
+                (backend.await?, true)
+            }
+            Some(sync) => match BackendBuilder::new(account_cfg.clone(), ctx.clone())
+                .build()
+                .await
+            {
+                Ok(backend) => (backend, false),
+                Err(cause) => {
+                    writeln!(
+                        err,
+                        "Cannot connect to IMAP, switching to offline mode: {cause}"
+                    )?;
+                    let backend =
+                        offline::build_offline_backend(account_cfg.clone(), ctx.clone(), sync);
+                    (backend.await?, true)
+                }
+            },
+            None if opts.offline => {
+                return Err(eyre!("offline mode requires an IMAP configuration"))
+            }
+            None => {
+                let backend = BackendBuilder::new(account_cfg.clone(), ctx.clone());
+                (backend.build().await?, false)
+            }
+        };
+
+        #[cfg(not(feature = "sync"))]
+        if opts.offline {
+            return Err(eyre!("offline mode requires the sync feature"));
+        }
+
+        #[cfg(not(feature = "sync"))]
+        let backend = BackendBuilder::new(account_cfg.clone(), ctx.clone())
+            .build()
+            .await?;
+
+        // in offline mode, copies of sent messages are saved to the
+        // local cache then uploaded by the next synchronization
+        let backend = Arc::new(backend);
+
+        let queue = SendQueue::new(
+            backend.clone(),
+            Outbox::new(&account_cfg)?,
+            repl_cfg.send_delay(),
+        );
+
+        queue.spawn_outbox_flusher(repl_cfg.send_outbox_interval());
+
+        let address_book = AddressBook::new(&account_cfg)?;
+        let templates = NamedTemplates::new(repl_cfg.templates_dir())?;
+        let identities = Identities::new(account_cfg.clone(), &toml_account_cfg);
+
+        let mut session = Self {
+            account_cfg,
+            toml_account_cfg,
+            repl_cfg,
+            keybinds,
+            theme,
+            mark_seen,
+            ctx,
+            backend,
+            queue,
+            folder: None,
+            address_book,
+            templates,
+            identities,
+
+            #[cfg(feature = "sync")]
+            synchronizer,
+            #[cfg(feature = "sync")]
+            journal,
+            #[cfg(feature = "sync")]
+            offline,
+
+            input,
+            out,
+            err,
+        };
+
+        #[cfg(feature = "sync")]
+        if !session.offline {
+            session.replay_journal().await?;
+        }
+
+        writeln!(session.out)?;
+
+        // synchronizing while offline would apply journaled changes to
+        // IMAP before the journal gets replayed
+        #[cfg(feature = "sync")]
+        if !session.offline {
+            session.spawn_sync();
+        }
+
+        // a missing vCard directory should not prevent the REPL from
+        // starting up
+        if let Some(dir) = session.repl_cfg.contacts_vcard_dir() {
+            match session.address_book.import_vcard_dir(dir) {
+                Ok(_) => session.address_book.save()?,
+                Err(err) => writeln!(session.err, "Cannot import contacts: {err}")?,
+            }
+        }
+
+        Ok(session)
+    }
+
+    /// The currently selected folder.
+    pub fn folder(&self) -> Option<&str> {
+        self.folder.as_deref()
+    }
+
+    pub fn prompt(&self) -> ThemedPrompt {
+        #[cfg(feature = "sync")]
+        let name = match self.offline {
+            true => "himalaya-repl (offline)",
+            false => "himalaya-repl",
+        };
+        #[cfg(not(feature = "sync"))]
+        let name = "himalaya-repl";
+
+        let prompt = match self.folder.as_ref() {
+            Some(folder) => DefaultPrompt::new(
+                DefaultPromptSegment::Basic(String::from(name)),
+                DefaultPromptSegment::Basic(format!("[{folder}]")),
+            ),
+            None => DefaultPrompt::new(
+                DefaultPromptSegment::Basic(String::from(name)),
+                DefaultPromptSegment::Empty,
+            ),
+        };
+
+        self.theme.prompt(prompt)
+    }
+
+    /// Executes command lines read from the input until the input is
+    /// over, then flushes the send queue.
+    ///
+    /// Command errors are reported without ending the session, so
+    /// that queued messages are still sent.
+    pub async fn run(&mut self) -> Result<()> {
+        loop {
+            for status in self.queue.status() {
+                writeln!(self.out, "{status}")?;
+            }
+
+            #[cfg(feature = "sync")]
+            for report in self.synchronizer.iter().flat_map(Synchronizer::reports) {
+                writeln!(self.out, "{report}")?;
+            }
+
+            let prompt = self.prompt();
+
+            match self.input.read_line(&prompt)? {
+                Some(line) => {
+                    if let Err(err) = self.execute(&line).await {
+                        writeln!(self.err, "{err}")?;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        self.queue.flush().await;
+
+        for status in self.queue.status() {
+            writeln!(self.out, "{status}")?;
+        }
+
+        writeln!(self.out, "Bye!")?;
+        Ok(())
+    }
+
+    /// Executes the given command line.
+    pub async fn execute(&mut self, line: &str) -> Result<()> {
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => (),
+            ["help" | "h", ..] => {
+                writeln!(self.out, "Available commands: {}", COMMANDS.join(", "))?;
+            }
+            ["folders", ..] => {
+                let folders = self.backend.list_folders().await?;
+                let table = FoldersTable::from(folders).with_theme(self.theme.clone());
+                writeln!(self.out, "{table}")?;
+            }
+            ["select", args @ ..] => {
+                let folder = match args.first() {
+                    Some(folder) => folder.to_string(),
+                    None => {
+                        let folders = self.backend.list_folders().await?;
+                        let folders = folders.into_iter().map(|f| f.name);
+                        input::item(&mut *self.input, "Select a folder:", folders)?
+                    }
+                };
+
+                self.folder = Some(folder);
+            }
+            ["unselect", ..] => {
+                self.folder = None;
+            }
+            ["list", ..] => {
+                let Some(folder) = self.selected_folder()? else {
+                    return Ok(());
+                };
+
+                let id_mapper = self.id_mapper(&folder)?;
+                let envelopes = self
+                    .backend
+                    .list_envelopes(
+                        &folder,
+                        ListEnvelopesOptions {
+                            page_size: 10,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                let mut envelopes = Envelopes::try_from_lib(
+                    self.account_cfg.clone(),
+                    &id_mapper,
+                    &folder,
+                    envelopes,
+                )?;
+
+                for envelope in envelopes.iter() {
+                    self.address_book.harvest(&envelope.from);
+                    self.address_book.harvest(&envelope.to);
+                }
+                self.address_book.save()?;
+
+                self.fetch_sizes(&folder, &id_mapper, &mut envelopes)
+                    .await?;
+
+                let outgoing = self.is_outgoing(&folder);
+                let table = envelopes_table(
+                    &self.account_cfg,
+                    &self.toml_account_cfg,
+                    &self.theme,
+                    envelopes,
+                    outgoing,
+                );
+
+                writeln!(self.out, "{table}")?;
+            }
+            [cmd @ ("read" | "peek"), args @ ..] => {
+                let Some(folder) = self.selected_folder()? else {
+                    return Ok(());
+                };
+
+                let Some(id) = self.parse_id_or_prompt(&folder, args.first().copied())? else {
+                    return Ok(());
+                };
+
+                let id = Id::single(id);
+                let emails = self.backend.peek_messages(&folder, &id).await?;
+
+                if self.mark_seen && *cmd == "read" {
+                    // the journal entry is built before flagging, a message
+                    // which cannot be journaled is still shown but its Seen
+                    // flag stays local
+                    #[cfg(feature = "sync")]
+                    let entry = match (self.offline, emails.first()) {
+                        (true, Some(email)) => {
+                            let op = JournalOp::AddFlag {
+                                flag: envelope::Flag::Seen,
+                            };
+
+                            match JournalEntry::from_message(&folder, email, op) {
+                                Ok(entry) => Some(entry),
+                                Err(err) => {
+                                    writeln!(
+                                        self.err,
+                                        "Cannot journal, Seen flag not synced: {err}"
+                                    )?;
+                                    None
+                                }
+                            }
+                        }
+                        _ => None,
+                    };
+
+                    self.backend.add_flag(&folder, &id, Flag::Seen).await?;
+
+                    #[cfg(feature = "sync")]
+                    if let Some(entry) = entry {
+                        self.journal.push(entry)?;
+                    }
+                }
+
+                for email in emails.to_vec() {
+                    self.address_book.harvest_message(email)?;
+                }
+                self.address_book.save()?;
+
+                let reader = Reader::new(self.theme.clone())
+                    .with_format(self.account_cfg.get_message_read_format())
+                    .with_some_width(terminal::size().ok().map(|(width, _)| width))
+                    .with_some_signature_delim(self.account_cfg.signature_delim.clone());
+
+                let tpls = message::to_read_tpls(&self.account_cfg, &emails, &reader).await?;
+                writeln!(self.out, "{tpls}")?;
+            }
+            ["write" | "compose", args @ ..] => {
+                let Some((args, template)) = take_option(args, "--template") else {
+                    writeln!(self.err, "The --template option requires a template name")?;
+                    return Ok(());
+                };
+
+                let identity_cfg = self.identities.current().config.clone();
+                let sign = args.contains(&"--sign");
+                let encrypt = args.contains(&"--encrypt");
+
+                let to = match args.iter().find(|arg| !arg.starts_with("--")) {
+                    Some(query) => match self.resolve_recipient(query)? {
+                        Some(to) => Some(to),
+                        None => return Ok(()),
+                    },
+                    None => None,
+                };
+
+                if args.contains(&"--inline") {
+                    if template.is_some() {
+                        writeln!(
+                            self.err,
+                            "The --template option cannot be used with --inline"
+                        )?;
+                        return Ok(());
+                    }
+
+                    let tpl = compose::compose_inline(
+                        identity_cfg.clone(),
+                        &mut *self.out,
+                        &self.keybinds,
+                        &self.address_book.addresses(),
+                        to,
+                    )
+                    .await?;
+
+                    let Some(tpl) = tpl else {
+                        writeln!(self.out, "Composition aborted")?;
+                        return Ok(());
+                    };
+
+                    let tpl = pgp::wrap_tpl(&tpl, sign, encrypt).into();
+
+                    editor::edit_tpl_inline(
+                        identity_cfg.clone(),
+                        &self.backend,
+                        &self.queue,
+                        &self.repl_cfg,
+                        &mut Io {
+                            input: &mut *self.input,
+                            out: &mut *self.out,
+                            err: &mut *self.err,
+                        },
+                        DraftOrigin::New,
+                        tpl,
+                    )
+                    .await?;
+                } else {
+                    let mut tpl = Message::new_tpl_builder(identity_cfg.clone())
+                        .with_headers(to.map(|to| ("To", to)));
+
+                    if let Some(name) = template {
+                        let Some(content) = self.templates.get(name)? else {
+                            writeln!(self.err, "{name}: template not found")?;
+                            return Ok(());
+                        };
+
+                        tpl = tpl.with_body(TemplateVars::default().render(&content));
+                    }
+
+                    let tpl = tpl.build().await?;
+                    let tpl = pgp::wrap_tpl(&tpl, sign, encrypt).into();
+
+                    editor::edit_tpl_with_editor(
+                        identity_cfg.clone(),
+                        &self.backend,
+                        &self.queue,
+                        &self.repl_cfg,
+                        &mut Io {
+                            input: &mut *self.input,
+                            out: &mut *self.out,
+                            err: &mut *self.err,
+                        },
+                        DraftOrigin::New,
+                        tpl,
+                    )
+                    .await?;
+                }
+            }
+            ["reply", args @ ..] => {
+                let Some(folder) = self.selected_folder()? else {
+                    return Ok(());
+                };
+
+                let Some((args, template)) = take_option(args, "--template") else {
+                    writeln!(self.err, "The --template option requires a template name")?;
+                    return Ok(());
+                };
+
+                let template = match template {
+                    Some(name) => match self.templates.get(name)? {
+                        Some(content) => Some(content),
+                        None => {
+                            writeln!(self.err, "{name}: template not found")?;
+                            return Ok(());
+                        }
+                    },
+                    None => None,
+                };
+
+                let Some(id) = self.parse_id_or_prompt(&folder, args.first().copied())? else {
+                    return Ok(());
+                };
+
+                let reply_all = self.input.bool("Reply to all recipients?", false)?;
+
+                let messages = self.backend.get_messages(&folder, &Id::single(&id)).await?;
+                let original = messages.first().ok_or(eyre!("cannot find message {id}"))?;
+
+                // replies are sent from the identity the original
+                // message was sent to
+                let identity = self.identities.find_for_reply(original)?;
+                let identity_cfg = identity.config.clone();
+
+                if identity.name != self.identities.current().name {
+                    writeln!(
+                        self.out,
+                        "Replying as {} <{}>",
+                        identity.name, identity_cfg.email
+                    )?;
+                }
+
+                let mut tpl = original
+                    .to_reply_tpl_builder(identity_cfg.clone())
+                    .with_reply_all(reply_all);
+
+                if let Some(content) = template {
+                    let vars = TemplateVars::from_message(original)?;
+                    tpl = tpl.with_body(vars.render(&content));
+                }
+
+                let tpl = tpl.build().await?;
+
+                let origin = DraftOrigin::Reply { folder, id };
+
+                editor::edit_tpl_with_editor(
+                    identity_cfg.clone(),
+                    &self.backend,
+                    &self.queue,
+                    &self.repl_cfg,
+                    &mut Io {
+                        input: &mut *self.input,
+                        out: &mut *self.out,
+                        err: &mut *self.err,
+                    },
+                    origin,
+                    tpl,
+                )
+                .await?;
+            }
+            ["forward", args @ ..] => {
+                let Some(folder) = self.selected_folder()? else {
+                    return Ok(());
+                };
+
+                let attach = args.contains(&"--attach");
+                let id = args.iter().copied().find(|arg| !arg.starts_with("--"));
+
+                let Some(id) = self.parse_id_or_prompt(&folder, id)? else {
+                    return Ok(());
+                };
+
+                let messages = self.backend.get_messages(&folder, &Id::single(&id)).await?;
+                let message = messages.first().ok_or(eyre!("cannot find message"))?;
+
+                let identity_cfg = self.identities.current().config.clone();
+                let mut tpl = message.to_forward_tpl_builder(identity_cfg.clone());
+
+                // forces the original message to be attached as a
+                // message/rfc822 part, whatever the configured style
+                if attach {
+                    tpl = tpl.with_posting_style(ForwardTemplatePostingStyle::Attached);
+                }
+
+                let tpl = tpl.build().await?;
+
+                let origin = DraftOrigin::Forward { folder, id };
+
+                editor::edit_tpl_with_editor(
+                    identity_cfg.clone(),
+                    &self.backend,
+                    &self.queue,
+                    &self.repl_cfg,
+                    &mut Io {
+                        input: &mut *self.input,
+                        out: &mut *self.out,
+                        err: &mut *self.err,
+                    },
+                    origin,
+                    tpl,
+                )
+                .await?;
+            }
+            ["bounce", args @ ..] => {
+                let Some(folder) = self.selected_folder()? else {
+                    return Ok(());
+                };
+
+                let [alias, query] = args else {
+                    writeln!(self.err, "Usage: bounce <id> <address>")?;
+                    return Ok(());
+                };
+
+                let Some(id) = self.parse_id_or_prompt(&folder, Some(alias))? else {
+                    return Ok(());
+                };
+
+                let Some(to) = self.resolve_recipient(query)? else {
+                    return Ok(());
+                };
+
+                let Some(addr) = message::parse_address(&to) else {
+                    writeln!(self.err, "{to}: invalid address")?;
+                    return Ok(());
+                };
+
+                let messages = self.backend.peek_messages(&folder, &Id::single(id)).await?;
+                let original = messages.first().ok_or(eyre!("cannot find message"))?;
+                let bounce = message::to_bounce(&self.account_cfg, original.raw()?, &to);
+
+                backend::send_message_to(
+                    &self.toml_account_cfg,
+                    &self.backend,
+                    &self.ctx.sending_backend,
+                    &self.account_cfg.email,
+                    &addr,
+                    &bounce,
+                )
+                .await?;
+
+                writeln!(self.out, "Message {alias} successfully bounced to {to}")?;
+            }
+            ["drafts", args @ ..] => {
+                let drafts = LocalDrafts::new(&self.account_cfg)?;
+
+                match args {
+                    [] | ["list"] => {
+                        writeln!(self.out, "{}", LocalDraftsTable::from(drafts.list()?))?;
+                    }
+                    ["resume", id] => {
+                        let Some(draft) = drafts.get(id)? else {
+                            writeln!(self.err, "{id}: draft not found")?;
+                            return Ok(());
+                        };
+
+                        editor::edit_local_draft(
+                            self.account_cfg.clone(),
+                            &self.backend,
+                            &self.queue,
+                            &self.repl_cfg,
+                            &mut Io {
+                                input: &mut *self.input,
+                                out: &mut *self.out,
+                                err: &mut *self.err,
+                            },
+                            &draft,
+                        )
+                        .await?;
+                    }
+                    ["delete", id] => {
+                        let Some(draft) = drafts.get(id)? else {
+                            writeln!(self.err, "{id}: draft not found")?;
+                            return Ok(());
+                        };
+
+                        draft.remove()?;
+                        writeln!(self.out, "Local draft {id} successfully deleted")?;
+                    }
+                    ["remote"] => {
+                        let id_mapper = self.id_mapper(DRAFTS)?;
+                        let envelopes = self
+                            .backend
+                            .list_envelopes(
+                                DRAFTS,
+                                ListEnvelopesOptions {
+                                    page_size: 10,
+                                    ..Default::default()
+                                },
+                            )
+                            .await?;
+                        let mut envelopes = Envelopes::try_from_lib(
+                            self.account_cfg.clone(),
+                            &id_mapper,
+                            DRAFTS,
+                            envelopes,
+                        )?;
+
+                        self.fetch_sizes(DRAFTS, &id_mapper, &mut envelopes).await?;
+
+                        let table = envelopes_table(
+                            &self.account_cfg,
+                            &self.toml_account_cfg,
+                            &self.theme,
+                            envelopes,
+                            true,
+                        );
+                        writeln!(self.out, "{table}")?;
+                    }
+                    _ => {
+                        writeln!(
+                            self.err,
+                            "Usage: drafts [list | resume <id> | delete <id> | remote]"
+                        )?;
+                    }
+                }
+            }
+            ["edit-draft", args @ ..] => {
+                let Some(id) = self.parse_id_or_prompt(DRAFTS, args.first().copied())? else {
+                    return Ok(());
+                };
+
+                let account_cfg = &self.account_cfg;
+                let tpl = self
+                    .backend
+                    .peek_messages(DRAFTS, &Id::single(&id))
+                    .await?
+                    .first()
+                    .ok_or(eyre!("cannot find draft {id}"))?
+                    .to_read_tpl(account_cfg, |tpl| {
+                        tpl.with_show_only_headers(account_cfg.get_message_write_headers())
+                            .with_show_multiparts(true)
+                    })
+                    .await?;
+
+                let origin = DraftOrigin::RemoteDraft { id };
+
+                editor::edit_tpl_with_editor(
+                    self.account_cfg.clone(),
+                    &self.backend,
+                    &self.queue,
+                    &self.repl_cfg,
+                    &mut Io {
+                        input: &mut *self.input,
+                        out: &mut *self.out,
+                        err: &mut *self.err,
+                    },
+                    origin,
+                    tpl,
+                )
+                .await?;
+            }
+            ["contacts", args @ ..] => match args {
+                [] | ["search"] => {
+                    let contacts = ContactsTable::from(self.address_book.search(""));
+                    writeln!(self.out, "{contacts}")?;
+                }
+                ["search", query @ ..] => {
+                    let query = query.join(" ");
+                    let contacts = ContactsTable::from(self.address_book.search(&query));
+                    writeln!(self.out, "{contacts}")?;
+                }
+                ["add", addr, name @ ..] => {
+                    let name = Some(name.join(" "));
+                    self.address_book.add(addr, name);
+                    self.address_book.save()?;
+                    writeln!(self.out, "Contact {addr} successfully added")?;
+                }
+                ["remove", addr] => {
+                    if self.address_book.remove(addr).is_some() {
+                        self.address_book.save()?;
+                        writeln!(self.out, "Contact {addr} successfully removed")?;
+                    } else {
+                        writeln!(self.err, "{addr}: contact not found")?;
+                    }
+                }
+                ["import", dir] => match self.address_book.import_vcard_dir(dir) {
+                    Ok(count) => {
+                        self.address_book.save()?;
+                        writeln!(self.out, "{count} addresses successfully imported")?;
+                    }
+                    Err(err) => writeln!(self.err, "Cannot import contacts: {err}")?,
+                },
+                _ => {
+                    writeln!(
+                        self.err,
+                        "Usage: contacts [search <query> | add <address> [name] | remove <address> | import <dir>]"
+                    )?;
+                }
+            },
+            ["copy", args @ ..] => {
+                let Some(source) = self.selected_folder()? else {
+                    return Ok(());
+                };
+
+                let Some(id) = self.parse_id_or_prompt(&source, args.first().copied())? else {
+                    return Ok(());
+                };
+
+                let target = match args.get(1) {
+                    Some(target) => target.to_string(),
+                    None => self.select_target_folder(&source).await?,
+                };
+
+                self.backend
+                    .copy_messages(&source, &target, &Id::single(id))
+                    .await?;
+            }
+            ["move", args @ ..] => {
+                let Some(source) = self.selected_folder()? else {
+                    return Ok(());
+                };
+
+                let Some(id) = self.parse_id_or_prompt(&source, args.first().copied())? else {
+                    return Ok(());
+                };
+
+                let id = Id::single(id);
+
+                let target = match args.get(1) {
+                    Some(target) => target.to_string(),
+                    None => self.select_target_folder(&source).await?,
+                };
+
+                #[cfg(feature = "sync")]
+                let entry = match self.offline {
+                    true => {
+                        let op = JournalOp::Move {
+                            target: target.clone(),
+                        };
+                        Some(JournalEntry::new(&self.backend, &source, &id, op).await?)
+                    }
+                    false => None,
+                };
+
+                self.backend.move_messages(&source, &target, &id).await?;
+
+                #[cfg(feature = "sync")]
+                if let Some(entry) = entry {
+                    self.journal.push(entry)?;
+                }
+            }
+            ["delete", args @ ..] => {
+                let Some(folder) = self.selected_folder()? else {
+                    return Ok(());
+                };
+
+                let Some(id) = self.parse_id_or_prompt(&folder, args.first().copied())? else {
+                    return Ok(());
+                };
+
+                let id = Id::single(id);
+
+                #[cfg(feature = "sync")]
+                let entry = match self.offline {
+                    true => {
+                        let op = JournalOp::Delete;
+                        Some(JournalEntry::new(&self.backend, &folder, &id, op).await?)
+                    }
+                    false => None,
+                };
+
+                self.backend.delete_messages(&folder, &id).await?;
+
+                #[cfg(feature = "sync")]
+                if let Some(entry) = entry {
+                    self.journal.push(entry)?;
+                }
+            }
+            ["send", id, args @ ..] => {
+                let Some(draft) = LocalDrafts::new(&self.account_cfg)?.get(id)? else {
+                    writeln!(self.err, "{id}: draft not found")?;
+                    return Ok(());
+                };
+
+                let tpl = draft.read()?;
+
+                if pgp::is_encrypted_tpl(&tpl) {
+                    let missing = pgp::missing_keys(&self.account_cfg, &tpl).await?;
+
+                    if !missing.is_empty() {
+                        let missing = missing.join(", ");
+                        writeln!(
+                            self.err,
+                            "Cannot encrypt the message, no public key found for: {missing}"
+                        )?;
+                        return Ok(());
+                    }
+                }
+
+                let email = editor::compile_tpl(&self.account_cfg, &tpl).await?;
+
+                match args {
+                    [] if self.queue.delay().is_zero() => {
+                        writeln!(self.out, "Sending email…")?;
+                        let report = self.queue.send(&draft, &email).await?;
+                        writeln!(self.out, "{report}")?;
+                    }
+                    [] => {
+                        self.queue.push(draft, email);
+                        writeln!(
+                            self.out,
+                            "Email will be sent in {}s, type `undo` to cancel",
+                            self.queue.delay().as_secs()
+                        )?;
+                    }
+                    ["--at", send_at @ ..] if !send_at.is_empty() => {
+                        let send_at = outbox::parse_send_at(&send_at.join(" "))?;
+                        let message = self.queue.schedule(&draft, &email, send_at).await?;
+                        writeln!(self.out, "Email successfully scheduled as {}", message.id)?;
+                    }
+                    _ => {
+                        writeln!(self.err, "Usage: send <draft-id> [--at <YYYY-MM-DDTHH:MM>]")?;
+                    }
+                }
+            }
+            ["outbox", args @ ..] => match args {
+                [] | ["list"] => {
+                    let messages = OutboxTable::from(self.queue.outbox().list()?);
+                    writeln!(self.out, "{messages}")?;
+                }
+                ["retry"] => {
+                    self.queue.flush_outbox(true).await?;
+                }
+                ["retry", id] => {
+                    if !self.queue.retry(id).await? {
+                        writeln!(self.err, "{id}: outbox message not found")?;
+                    }
+                }
+                ["cancel", id] => {
+                    let Some(message) = self.queue.outbox().get(id)? else {
+                        writeln!(self.err, "{id}: outbox message not found")?;
+                        return Ok(());
+                    };
+
+                    message.remove()?;
+                    writeln!(self.out, "Outbox message {id} successfully cancelled")?;
+                }
+                _ => {
+                    writeln!(
+                        self.err,
+                        "Usage: outbox [list | retry [<id>] | cancel <id>]"
+                    )?;
+                }
+            },
+            ["identity", args @ ..] => match args {
+                [] | ["list"] => {
+                    writeln!(self.out, "{}", IdentitiesTable::from(&self.identities))?;
+                }
+                [name] => {
+                    let Some(identity) = self.identities.select(name) else {
+                        writeln!(self.err, "{name}: identity not found")?;
+                        return Ok(());
+                    };
+
+                    writeln!(
+                        self.out,
+                        "Identity {} <{}> selected",
+                        identity.name, identity.config.email
+                    )?;
+                }
+                _ => {
+                    writeln!(self.err, "Usage: identity [list | <name>]")?;
+                }
+            },
+            ["templates", ..] => {
+                let names = self.templates.list()?;
+
+                if names.is_empty() {
+                    writeln!(self.out, "No template found")?;
+                } else {
+                    writeln!(self.out, "Available templates: {}", names.join(", "))?;
+                }
+            }
+            #[cfg(feature = "sync")]
+            ["sync", ..] => {
+                let Some(sync) = &self.synchronizer else {
+                    writeln!(self.err, "Synchronization requires an IMAP configuration")?;
+                    return Ok(());
+                };
+
+                if self.offline {
+                    writeln!(
+                        self.err,
+                        "Offline mode: type `online` to replay offline changes first"
+                    )?;
+                    return Ok(());
+                }
+
+                writeln!(
+                    self.out,
+                    "Synchronizing IMAP folders with {:?}…",
+                    sync.dir()
+                )?;
+
+                // the progress is received while synchronizing, so that it
+                // goes through the session output
+                let (progress, mut progress_rx) = mpsc::unbounded_channel();
+                let summary = sync.sync(Some(progress));
+                tokio::pin!(summary);
+
+                let summary = loop {
+                    tokio::select! {
+                        summary = &mut summary => break summary?,
+                        Some((done, total)) = progress_rx.recv() => {
+                            write!(self.out, "\rSynchronizing… {done}/{total} change(s)")?;
+                            self.out.flush()?;
+                        }
+                    }
+                };
+
+                writeln!(self.out)?;
+                writeln!(self.out, "{summary}")?;
+                writeln!(self.out, "{}", summary.to_line())?;
+            }
+            #[cfg(feature = "sync")]
+            ["online", ..] => {
+                if !self.offline {
+                    writeln!(self.err, "Already online")?;
+                    return Ok(());
+                }
+
+                writeln!(self.out, "Connecting to IMAP…")?;
+                let online = BackendBuilder::new(self.account_cfg.clone(), self.ctx.clone())
+                    .build()
+                    .await?;
+
+                self.backend = Arc::new(online);
+                self.queue.set_backend(self.backend.clone());
+                self.offline = false;
+                writeln!(self.out, "Back online")?;
+
+                self.replay_journal().await?;
+                self.spawn_sync();
+            }
+            #[cfg(feature = "sync")]
+            ["journal", args @ ..] => match args {
+                [] | ["list"] => {
+                    writeln!(self.out, "{}", JournalTable::from(self.journal.entries()?))?;
+                }
+                ["discard", id] => {
+                    if self.journal.discard(id)?.is_some() {
+                        writeln!(self.out, "Journal entry {id} successfully discarded")?;
+                    } else {
+                        writeln!(self.err, "{id}: journal entry not found")?;
+                    }
+                }
+                _ => {
+                    writeln!(self.err, "Usage: journal [list | discard <id>]")?;
+                }
+            },
+            ["undo", ..] => match self.queue.undo() {
+                Some(pending) => writeln!(
+                    self.out,
+                    "Sending of \"{}\" cancelled, the message is kept as local draft {}",
+                    pending.subject, pending.draft.id
+                )?,
+                None => writeln!(self.err, "There is no pending message to cancel")?,
+            },
+            [cmd, ..] => {
+                writeln!(self.err, "{cmd}: command not found")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the selected folder, or reports that no folder is
+    /// selected.
+    fn selected_folder(&mut self) -> Result<Option<String>> {
+        match self.folder.clone() {
+            Some(folder) => Ok(Some(folder)),
+            None => {
+                writeln!(self.err, "Please select a folder first")?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn select_target_folder(&mut self, source: &str) -> Result<String> {
+        let folders = self.backend.list_folders().await?;
+        let folders = folders.into_iter().map(|f| f.name).filter(|f| f != source);
+        input::item(&mut *self.input, "Select a target folder:", folders)
+    }
+
+    /// Builds the mapper between the envelope identifiers of the
+    /// given folder and the aliases shown to the user.
+    ///
+    /// Maildir and Notmuch identifiers are not numbers, they are
+    /// aliased with incremental numbers instead.
+    fn id_mapper(&self, folder: &str) -> Result<IdMapper> {
+        #[cfg(feature = "sync")]
+        if self.offline {
+            return IdMapper::new(&self.account_cfg, folder);
+        }
+
+        // identifiers come from the backend listing envelopes
+        match self.ctx.list_envelopes_backend() {
+            #[cfg(feature = "maildir")]
+            BackendKind::Maildir => IdMapper::new(&self.account_cfg, folder),
+            #[cfg(feature = "notmuch")]
+            BackendKind::Notmuch => IdMapper::new(&self.account_cfg, folder),
+            _ => Ok(IdMapper::Dummy),
+        }
+    }
+
+    /// Parses the given envelope identifier argument, or prompts for
+    /// it if missing, then resolves it to the identifier of the
+    /// backend.
+    ///
+    /// Returns `None` if the argument is not a valid identifier, after
+    /// reporting it to the user.
+    fn parse_id_or_prompt(&mut self, folder: &str, arg: Option<&str>) -> Result<Option<String>> {
+        let alias: usize = match arg {
+            Some(arg) => match arg.parse() {
+                Ok(alias) => alias,
+                Err(_) => {
+                    writeln!(self.err, "{arg}: invalid envelope identifier")?;
+                    return Ok(None);
+                }
+            },
+            None => self.input.usize("Select an envelope identifier:", None)?,
+        };
+
+        let id = self.id_mapper(folder)?.get_id(alias)?;
+        Ok(Some(id))
+    }
+
+    /// Whether the given folder holds outgoing messages, in which
+    /// case recipients are more relevant than senders.
+    fn is_outgoing(&self, folder: &str) -> bool {
+        [SENT, DRAFTS]
+            .iter()
+            .any(|outgoing| self.account_cfg.get_folder_alias(outgoing) == folder)
+    }
+
+    /// Fetches the size of the given envelopes, only if the envelope
+    /// listing table shows it since it requires to peek messages.
+    async fn fetch_sizes(
+        &self,
+        folder: &str,
+        id_mapper: &IdMapper,
+        envelopes: &mut Envelopes,
+    ) -> Result<()> {
+        let columns = self.toml_account_cfg.envelope_list_table_columns();
+
+        if !columns.is_some_and(|columns| columns.iter().any(|c| c.kind == ColumnKind::Size)) {
+            return Ok(());
+        }
+
+        for envelope in envelopes.iter_mut() {
+            let id = Id::single(id_mapper.get_id(&envelope.id)?);
+            let messages = self.backend.peek_messages(folder, &id).await?;
+
+            if let Some(message) = messages.first() {
+                envelope.size = Some(message.raw()?.len());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the recipient given to the `write` command, either
+    /// from a raw address or from a search in the address book.
+    ///
+    /// Returns `None` if no contact matches the query, after
+    /// reporting it to the user.
+    fn resolve_recipient(&mut self, query: &str) -> Result<Option<String>> {
+        if query.contains('@') {
+            return Ok(Some(query.to_owned()));
+        }
+
+        let contacts = self.address_book.search(query);
+
+        match contacts.as_slice() {
+            [] => {
+                writeln!(self.err, "{query}: contact not found")?;
+                Ok(None)
+            }
+            [contact] => Ok(Some(contact.to_string())),
+            contacts => {
+                let contacts: Vec<String> = contacts.iter().map(ToString::to_string).collect();
+                let contact = input::item(&mut *self.input, "Select a recipient:", contacts)?;
+                Ok(Some(contact))
+            }
+        }
+    }
+
+    /// Replays the changes journaled while offline against the
+    /// current backend, and reports the outcome.
+    #[cfg(feature = "sync")]
+    async fn replay_journal(&mut self) -> Result<()> {
+        if self.journal.entries()?.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(self.out, "Replaying offline changes…")?;
+        let report = self.journal.replay(&self.backend).await?;
+        write!(self.out, "{report}")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "sync")]
+    fn spawn_sync(&self) {
+        if let (Some(sync), Some(interval)) = (&self.synchronizer, self.repl_cfg.sync_interval()) {
+            sync.spawn(interval);
+        }
+    }
+}
+
+fn context_builder(
+    account_cfg: &Arc<AccountConfig>,
+    toml_account_cfg: &TomlAccountConfig,
+) -> ContextBuilder {
+    ContextBuilder {
+        backend: toml_account_cfg
+            .backend
+            .clone()
+            .unwrap_or(BackendKind::None),
+        sending_backend: toml_account_cfg
+            .message
+            .as_ref()
+            .and_then(|c| c.send.as_ref())
+            .and_then(|c| c.backend.clone())
+            .map(Into::into)
+            .unwrap_or(BackendKind::None),
+        routes: toml_account_cfg.backends.clone().unwrap_or_default(),
+
+        #[cfg(feature = "imap")]
+        imap: toml_account_cfg
+            .imap
+            .as_ref()
+            .map(|imap| ImapContextBuilder::new(account_cfg.clone(), Arc::new(imap.clone()))),
+        #[cfg(feature = "maildir")]
+        maildir: toml_account_cfg.maildir.as_ref().map(|maildir| {
+            MaildirContextBuilder::new(account_cfg.clone(), Arc::new(maildir.clone()))
+        }),
+        #[cfg(feature = "notmuch")]
+        notmuch: toml_account_cfg.notmuch.as_ref().map(|notmuch| {
+            NotmuchContextBuilder::new(account_cfg.clone(), Arc::new(notmuch.clone()))
+        }),
+        #[cfg(feature = "smtp")]
+        smtp: toml_account_cfg
+            .smtp
+            .as_ref()
+            .map(|smtp| SmtpContextBuilder::new(account_cfg.clone(), Arc::new(smtp.clone()))),
+        #[cfg(feature = "sendmail")]
+        sendmail: toml_account_cfg.sendmail.as_ref().map(|sendmail| {
+            SendmailContextBuilder::new(account_cfg.clone(), Arc::new(sendmail.clone()))
+        }),
+        #[cfg(feature = "memory")]
+        memory: toml_account_cfg
+            .memory
+            .as_ref()
+            .map(|memory| MemoryContextBuilder::new(account_cfg.clone(), Arc::new(memory.clone()))),
+    }
+}
+
+/// Builds the theme of the session. Colours of the envelope listing
+/// table and of the read view set in the account config take
+/// precedence over the theme ones.
+fn theme(repl_cfg: &ReplConfig, toml_account_cfg: &TomlAccountConfig) -> Theme {
+    let theme = Theme::new(repl_cfg.theme());
+    let read_styles = toml_account_cfg.message_read_styles();
+
+    Theme {
+        envelope_id: toml_account_cfg
+            .envelope_list_table_id_color()
+            .or(theme.envelope_id),
+        envelope_flags: toml_account_cfg
+            .envelope_list_table_flags_color()
+            .or(theme.envelope_flags),
+        envelope_subject: toml_account_cfg
+            .envelope_list_table_subject_color()
+            .or(theme.envelope_subject),
+        envelope_sender: toml_account_cfg
+            .envelope_list_table_sender_color()
+            .or(theme.envelope_sender),
+        envelope_date: toml_account_cfg
+            .envelope_list_table_date_color()
+            .or(theme.envelope_date),
+        header_name: read_styles.header_name_color.or(theme.header_name),
+        header_value: read_styles.header_value_color.or(theme.header_value),
+        quotes: read_styles.quote_colors.unwrap_or(theme.quotes),
+        url: read_styles.url_color.or(theme.url),
+        signature: read_styles.signature_color.or(theme.signature),
+        ..theme
+    }
+}
+
+fn envelopes_table(
+    account_cfg: &AccountConfig,
+    toml_account_cfg: &TomlAccountConfig,
+    theme: &Theme,
+    envelopes: Envelopes,
+    outgoing: bool,
+) -> EnvelopesTable {
+    let width = terminal::size().ok().map(|(width, _)| width);
+
+    EnvelopesTable::from(envelopes)
+        .with_some_width(width)
+        .with_some_columns(toml_account_cfg.envelope_list_table_columns())
+        .with_outgoing(outgoing)
+        .with_some_datetime_fmt(Some(account_cfg.get_envelope_list_datetime_fmt()))
+        .with_datetime_local_tz(account_cfg.has_envelope_list_datetime_local_tz())
+        .with_some_datetime_mode(toml_account_cfg.envelope_list_datetime_mode())
+        .with_some_preset(toml_account_cfg.envelope_list_table_preset())
+        .with_some_unseen_char(toml_account_cfg.envelope_list_table_unseen_char())
+        .with_some_replied_char(toml_account_cfg.envelope_list_table_replied_char())
+        .with_some_flagged_char(toml_account_cfg.envelope_list_table_flagged_char())
+        .with_some_attachment_char(toml_account_cfg.envelope_list_table_attachment_char())
+        .with_theme(theme.clone())
+}
+
+/// Splits the given command arguments into the remaining arguments
+/// and the value of the given option, like `--template <name>`.
+///
+/// Returns `None` if the option is given without value.
+fn take_option<'a>(args: &[&'a str], option: &str) -> Option<(Vec<&'a str>, Option<&'a str>)> {
+    let mut rest = Vec::with_capacity(args.len());
+    let mut value = None;
+    let mut args = args.iter().peekable();
+
+    while let Some(arg) = args.next() {
+        if *arg == option {
+            value = Some(*args.next_if(|arg| !arg.starts_with("--"))?);
+        } else {
+            rest.push(*arg);
+        }
+    }
+
+    Some((rest, value))
+}
@@ -0,0 +1,259 @@
+//! Synchronization module.
+//!
+//! This module mirrors the IMAP folders of an account into a local
+//! Maildir cache, in both directions, using the synchronization
+//! machinery of `email-lib`. Synchronizations can be triggered by
+//! the `sync` command, or periodically in the background.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use comfy_table::{presets, Cell, ContentArrangement, Row, Table};
+use dirs::data_dir;
+use email::{
+    account::config::AccountConfig,
+    backend::BackendBuilder,
+    imap::{config::ImapConfig, ImapContextBuilder},
+    maildir::{config::MaildirConfig, MaildirContextBuilder},
+    sync::{hunk::EmailSyncHunk, SyncBuilder, SyncEvent},
+};
+use tokio::{sync::mpsc::UnboundedSender, task::JoinHandle};
+use tracing::debug;
+
+use crate::{account::config::TomlAccountConfig, config::ReplConfig};
+
+/// Changes applied to a folder during a synchronization.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FolderSyncSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub flags_changed: usize,
+    pub errors: usize,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyncSummary {
+    pub folders: BTreeMap<String, FolderSyncSummary>,
+}
+
+impl SyncSummary {
+    /// Summarizes the given synchronization in a single line.
+    pub fn to_line(&self) -> String {
+        let (added, removed, flags_changed, errors) =
+            self.folders
+                .values()
+                .fold((0, 0, 0, 0), |(added, removed, flags, errors), folder| {
+                    (
+                        added + folder.added,
+                        removed + folder.removed,
+                        flags + folder.flags_changed,
+                        errors + folder.errors,
+                    )
+                });
+
+        format!(
+            "Synchronized {} folder(s): {added} added, {removed} removed, \
+             {flags_changed} flag change(s), {errors} error(s)",
+            self.folders.len()
+        )
+    }
+}
+
+impl fmt::Display for SyncSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+
+        table
+            .load_preset(presets::ASCII_MARKDOWN)
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_header(Row::from([
+                Cell::new("FOLDER"),
+                Cell::new("ADDED"),
+                Cell::new("REMOVED"),
+                Cell::new("FLAGS"),
+                Cell::new("ERRORS"),
+            ]))
+            .add_rows(self.folders.iter().map(|(name, folder)| {
+                let mut row = Row::new();
+                row.max_height(1);
+                row.add_cell(Cell::new(name))
+                    .add_cell(Cell::new(folder.added))
+                    .add_cell(Cell::new(folder.removed))
+                    .add_cell(Cell::new(folder.flags_changed))
+                    .add_cell(Cell::new(folder.errors));
+                row
+            }));
+
+        writeln!(f)?;
+        write!(f, "{table}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
+
+/// Synchronizes the IMAP folders of an account with a local Maildir.
+#[derive(Clone)]
+pub struct Synchronizer {
+    account_config: Arc<AccountConfig>,
+    imap_config: Arc<ImapConfig>,
+    maildir_config: Arc<MaildirConfig>,
+    /// Prevents two synchronizations from running at the same time.
+    lock: Arc<tokio::sync::Mutex<()>>,
+    reports: Arc<Mutex<Vec<String>>>,
+}
+
+impl Synchronizer {
+    pub fn new(
+        account_config: Arc<AccountConfig>,
+        toml_account_config: &TomlAccountConfig,
+        repl_config: &ReplConfig,
+    ) -> Result<Self> {
+        let imap_config = toml_account_config.imap.clone().ok_or(eyre!(
+            "cannot synchronize account without IMAP configuration"
+        ))?;
+
+        let root_dir = match repl_config.sync_dir() {
+            Some(dir) => dir,
+            None => data_dir()
+                .ok_or(eyre!("cannot get XDG data directory"))?
+                .join("himalaya")
+                .join("sync"),
+        };
+
+        let maildir_config = MaildirConfig {
+            root_dir: root_dir.join(&account_config.name),
+            ..Default::default()
+        };
+
+        Ok(Self {
+            account_config,
+            imap_config: Arc::new(imap_config),
+            maildir_config: Arc::new(maildir_config),
+            lock: Default::default(),
+            reports: Default::default(),
+        })
+    }
+
+    /// Returns the directory of the local Maildir cache.
+    pub fn dir(&self) -> &PathBuf {
+        &self.maildir_config.root_dir
+    }
+
+    /// Returns a context builder of the local Maildir cache.
+    pub fn maildir_context(&self) -> MaildirContextBuilder {
+        MaildirContextBuilder::new(self.account_config.clone(), self.maildir_config.clone())
+    }
+
+    /// Synchronizes IMAP folders with the local Maildir, optionally
+    /// sending the progress as processed and total changes.
+    pub async fn sync(
+        &self,
+        progress: Option<UnboundedSender<(usize, usize)>>,
+    ) -> Result<SyncSummary> {
+        let _guard = self.lock.lock().await;
+
+        let left = BackendBuilder::new(self.account_config.clone(), self.maildir_context());
+
+        let right = BackendBuilder::new(
+            self.account_config.clone(),
+            ImapContextBuilder::new(self.account_config.clone(), self.imap_config.clone()),
+        );
+
+        let total = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let report = SyncBuilder::new(left, right)
+            .with_handler(move |evt| {
+                match evt {
+                    SyncEvent::GeneratedEmailPatch(patches) => {
+                        let hunks = patches.values().map(|hunks| hunks.len()).sum();
+                        total.store(hunks, Ordering::Relaxed);
+                    }
+                    SyncEvent::ProcessedEmailHunk(_) => {
+                        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                        let total = total.load(Ordering::Relaxed);
+
+                        if let Some(progress) = &progress {
+                            // the receiver may be gone, the progress
+                            // is only informative
+                            let _ = progress.send((done, total));
+                        }
+                    }
+                    _ => (),
+                }
+
+                async { Ok(()) }
+            })
+            .sync()
+            .await
+            .context("cannot synchronize IMAP folders with local Maildir")?;
+
+        let mut summary = SyncSummary::default();
+
+        for folder in report.folder.names {
+            summary.folders.entry(folder).or_default();
+        }
+
+        for (hunk, err) in report.email.patch {
+            let folder = match &hunk {
+                EmailSyncHunk::CopyThenCache(folder, ..)
+                | EmailSyncHunk::Delete(folder, ..)
+                | EmailSyncHunk::UpdateFlags(folder, ..) => folder,
+                // other hunks only update the synchronization cache
+                _ => continue,
+            };
+
+            let folder = summary.folders.entry(folder.clone()).or_default();
+
+            if let Some(err) = err {
+                debug!("cannot apply sync hunk {hunk:?}: {err}");
+                folder.errors += 1;
+                continue;
+            }
+
+            match hunk {
+                EmailSyncHunk::CopyThenCache(..) => folder.added += 1,
+                EmailSyncHunk::Delete(..) => folder.removed += 1,
+                _ => folder.flags_changed += 1,
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Spawns a task synchronizing at the given interval. Summaries
+    /// are collected as reports.
+    pub fn spawn(&self, interval: Duration) -> JoinHandle<()> {
+        let sync = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let report = match sync.sync(None).await {
+                    Ok(summary) => summary.to_line(),
+                    Err(err) => format!("Background synchronization failed: {err}"),
+                };
+
+                sync.reports.lock().unwrap().push(report);
+            }
+        })
+    }
+
+    /// Lists background synchronization reports since the last call.
+    pub fn reports(&self) -> Vec<String> {
+        std::mem::take(&mut self.reports.lock().unwrap())
+    }
+}
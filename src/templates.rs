@@ -0,0 +1,193 @@
+//! Named templates module.
+//!
+//! Named templates are canned bodies stored as plain files inside the
+//! templates directory, one file per template. They can contain
+//! variables, like `{sender_name}` or `{subject}`, substituted from
+//! the envelope of the original message when replying.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use dirs::config_dir;
+use email::message::Message;
+
+/// The directory of named templates.
+#[derive(Clone, Debug)]
+pub struct NamedTemplates {
+    dir: PathBuf,
+}
+
+impl NamedTemplates {
+    pub fn new(dir: Option<PathBuf>) -> Result<Self> {
+        let dir = match dir {
+            Some(dir) => dir,
+            None => config_dir()
+                .ok_or(eyre!("cannot get XDG config directory"))?
+                .join("himalaya")
+                .join("templates"),
+        };
+
+        Ok(Self::from_dir(dir))
+    }
+
+    pub fn from_dir(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_owned(),
+        }
+    }
+
+    /// Lists the names of available templates, which are the stems of
+    /// the files of the templates directory.
+    pub fn list(&self) -> Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let entries = fs::read_dir(&self.dir)
+            .with_context(|| format!("cannot read templates directory at {:?}", self.dir))?;
+
+        let mut names = Vec::new();
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if !path.is_file() {
+                continue;
+            }
+
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                names.push(name.to_owned());
+            }
+        }
+
+        names.sort();
+        Ok(names)
+    }
+
+    /// Reads the content of the template matching the given name,
+    /// whatever its file extension. Returns `None` if there is no
+    /// such template.
+    pub fn get(&self, name: &str) -> Result<Option<String>> {
+        if !self.dir.exists() {
+            return Ok(None);
+        }
+
+        let entries = fs::read_dir(&self.dir)
+            .with_context(|| format!("cannot read templates directory at {:?}", self.dir))?;
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.is_file() && path.file_stem().and_then(|stem| stem.to_str()) == Some(name) {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("cannot read template at {path:?}"))?;
+                return Ok(Some(content));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Values substituted to the variables of named templates.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TemplateVars {
+    pub sender_name: String,
+    pub sender_addr: String,
+    pub subject: String,
+    pub date: String,
+}
+
+impl TemplateVars {
+    /// Extracts variables from the envelope of the given message.
+    pub fn from_message(message: &Message) -> Result<Self> {
+        let message = message.parsed()?;
+        let sender = message.from().and_then(|addrs| addrs.first());
+
+        Ok(Self {
+            sender_name: sender
+                .and_then(|addr| addr.name().or(addr.address()))
+                .unwrap_or_default()
+                .to_owned(),
+            sender_addr: sender
+                .and_then(|addr| addr.address())
+                .unwrap_or_default()
+                .to_owned(),
+            subject: message.subject().unwrap_or_default().to_owned(),
+            date: message
+                .date()
+                .map(|date| date.to_rfc822())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn get(&self, var: &str) -> Option<&str> {
+        match var {
+            "sender_name" => Some(&self.sender_name),
+            "sender_addr" => Some(&self.sender_addr),
+            "subject" => Some(&self.subject),
+            "date" => Some(&self.date),
+            _ => None,
+        }
+    }
+
+    /// Substitutes variables of the given template content. Unknown
+    /// variables are left untouched.
+    ///
+    /// Variables are substituted in a single pass, so that values
+    /// containing variables, like a subject, are not substituted in
+    /// turn.
+    pub fn render(&self, content: &str) -> String {
+        let mut rendered = String::with_capacity(content.len());
+        let mut rest = content;
+
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let value = rest[1..]
+                .find('}')
+                .and_then(|end| Some((end + 2, self.get(&rest[1..end + 1])?)));
+
+            match value {
+                Some((len, value)) => {
+                    rendered.push_str(value);
+                    rest = &rest[len..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TemplateVars;
+
+    #[test]
+    fn render() {
+        let vars = TemplateVars {
+            sender_name: String::from("Bob"),
+            subject: String::from("About {date}"),
+            date: String::from("Mon, 1 Jan 2024"),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            vars.render("Hi {sender_name}, re: {subject} {unknown} {"),
+            "Hi Bob, re: About {date} {unknown} {"
+        );
+    }
+}
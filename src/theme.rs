@@ -0,0 +1,352 @@
+//! Theme module.
+//!
+//! A theme gathers the colours used across the REPL: in listing
+//! tables, in the prompt and in the read view. Colours and attributes
+//! are disabled when the `NO_COLOR` environment variable is set or
+//! when stdout is not a terminal.
+
+use std::{
+    borrow::Cow,
+    env,
+    ffi::OsString,
+    io::{self, IsTerminal},
+};
+
+use crossterm::style::{Attribute, Color, ContentStyle, Stylize};
+use reedline::{DefaultPrompt, Prompt, PromptEditMode, PromptHistorySearch};
+use serde::{Deserialize, Serialize};
+
+/// Represents the themes shipped with the REPL.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeKind {
+    /// Bright colours, for terminals with a dark background.
+    #[default]
+    Dark,
+    /// Dark colours, for terminals with a light background.
+    Light,
+    /// Colours of the Solarized palette.
+    Solarized,
+    /// No colour at all, only attributes like bold.
+    Monochrome,
+}
+
+/// Colours of the REPL. Empty colours leave the text untouched.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Theme {
+    pub envelope_id: Option<Color>,
+    pub envelope_flags: Option<Color>,
+    pub envelope_subject: Option<Color>,
+    pub envelope_sender: Option<Color>,
+    pub envelope_date: Option<Color>,
+
+    pub folder_name: Option<Color>,
+    pub folder_desc: Option<Color>,
+
+    pub prompt: Option<Color>,
+    pub prompt_indicator: Option<Color>,
+    pub prompt_folder: Option<Color>,
+
+    pub header_name: Option<Color>,
+    pub header_value: Option<Color>,
+    /// Colours of quoted lines, by quote depth. Quoted lines deeper
+    /// than the colours reuse them from the first one.
+    pub quotes: Vec<Color>,
+    pub url: Option<Color>,
+    pub signature: Option<Color>,
+
+    /// Whether colours and attributes are rendered at all.
+    pub styling: bool,
+}
+
+impl Theme {
+    /// Creates the theme of the given kind, styling only if the
+    /// environment allows it.
+    pub fn new(kind: ThemeKind) -> Self {
+        let theme = match kind {
+            ThemeKind::Dark => Self {
+                envelope_id: Some(Color::Red),
+                envelope_flags: None,
+                envelope_subject: Some(Color::Green),
+                envelope_sender: Some(Color::Blue),
+                envelope_date: Some(Color::DarkYellow),
+                folder_name: Some(Color::Blue),
+                folder_desc: Some(Color::Green),
+                prompt: Some(Color::Green),
+                prompt_indicator: Some(Color::Cyan),
+                prompt_folder: Some(Color::Magenta),
+                header_name: Some(Color::Blue),
+                header_value: None,
+                quotes: vec![Color::Cyan, Color::Magenta, Color::DarkYellow],
+                url: Some(Color::Blue),
+                signature: Some(Color::DarkGrey),
+                styling: true,
+            },
+            ThemeKind::Light => Self {
+                envelope_id: Some(Color::DarkRed),
+                envelope_flags: None,
+                envelope_subject: Some(Color::DarkGreen),
+                envelope_sender: Some(Color::DarkBlue),
+                envelope_date: Some(Color::DarkYellow),
+                folder_name: Some(Color::DarkBlue),
+                folder_desc: Some(Color::DarkGreen),
+                prompt: Some(Color::DarkGreen),
+                prompt_indicator: Some(Color::DarkCyan),
+                prompt_folder: Some(Color::DarkMagenta),
+                header_name: Some(Color::DarkBlue),
+                header_value: None,
+                quotes: vec![Color::DarkCyan, Color::DarkMagenta, Color::DarkYellow],
+                url: Some(Color::DarkBlue),
+                signature: Some(Color::Grey),
+                styling: true,
+            },
+            ThemeKind::Solarized => Self {
+                envelope_id: Some(solarized::RED),
+                envelope_flags: Some(solarized::BASE01),
+                envelope_subject: Some(solarized::GREEN),
+                envelope_sender: Some(solarized::BLUE),
+                envelope_date: Some(solarized::YELLOW),
+                folder_name: Some(solarized::BLUE),
+                folder_desc: Some(solarized::CYAN),
+                prompt: Some(solarized::GREEN),
+                prompt_indicator: Some(solarized::CYAN),
+                prompt_folder: Some(solarized::VIOLET),
+                header_name: Some(solarized::BLUE),
+                header_value: None,
+                quotes: vec![solarized::CYAN, solarized::VIOLET, solarized::YELLOW],
+                url: Some(solarized::BLUE),
+                signature: Some(solarized::BASE01),
+                styling: true,
+            },
+            ThemeKind::Monochrome => Self {
+                envelope_id: None,
+                envelope_flags: None,
+                envelope_subject: None,
+                envelope_sender: None,
+                envelope_date: None,
+                folder_name: None,
+                folder_desc: None,
+                prompt: None,
+                prompt_indicator: None,
+                prompt_folder: None,
+                header_name: None,
+                header_value: None,
+                quotes: vec![],
+                url: None,
+                signature: None,
+                styling: true,
+            },
+        };
+
+        theme.with_styling(styling_enabled())
+    }
+
+    pub fn with_styling(mut self, styling: bool) -> Self {
+        self.styling = styling;
+        self
+    }
+
+    /// Paints the given text with the given colour, if styling is
+    /// enabled.
+    pub fn paint<'a>(&self, text: &'a str, color: Option<Color>) -> Cow<'a, str> {
+        match color {
+            Some(color) if self.styling => Cow::Owned(text.with(color).to_string()),
+            _ => Cow::Borrowed(text),
+        }
+    }
+
+    /// Paints the given text with the given colour and attribute, if
+    /// styling is enabled.
+    pub fn style<'a>(
+        &self,
+        text: &'a str,
+        color: Option<Color>,
+        attribute: Attribute,
+    ) -> Cow<'a, str> {
+        if !self.styling || text.is_empty() {
+            return Cow::Borrowed(text);
+        }
+
+        let mut style = ContentStyle::new().attribute(attribute);
+        style.foreground_color = color;
+        Cow::Owned(style.apply(text).to_string())
+    }
+
+    /// Wraps the given prompt, so that it uses the colours of the
+    /// theme.
+    pub fn prompt(&self, prompt: DefaultPrompt) -> ThemedPrompt {
+        ThemedPrompt {
+            prompt,
+            color: self.prompt,
+            indicator_color: self.prompt_indicator,
+            right_color: self.prompt_folder,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new(ThemeKind::default())
+    }
+}
+
+impl From<ThemeKind> for Theme {
+    fn from(kind: ThemeKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+mod solarized {
+    use crossterm::style::Color;
+
+    pub const YELLOW: Color = Color::Rgb {
+        r: 181,
+        g: 137,
+        b: 0,
+    };
+    pub const RED: Color = Color::Rgb {
+        r: 220,
+        g: 50,
+        b: 47,
+    };
+    pub const VIOLET: Color = Color::Rgb {
+        r: 108,
+        g: 113,
+        b: 196,
+    };
+    pub const BLUE: Color = Color::Rgb {
+        r: 38,
+        g: 139,
+        b: 210,
+    };
+    pub const CYAN: Color = Color::Rgb {
+        r: 42,
+        g: 161,
+        b: 152,
+    };
+    pub const GREEN: Color = Color::Rgb {
+        r: 133,
+        g: 153,
+        b: 0,
+    };
+    pub const BASE01: Color = Color::Rgb {
+        r: 88,
+        g: 110,
+        b: 117,
+    };
+}
+
+/// Whether colours and attributes should be rendered, as defined by
+/// <https://no-color.org>.
+pub fn styling_enabled() -> bool {
+    styling_enabled_with(env::var_os("NO_COLOR"), io::stdout().is_terminal())
+}
+
+fn styling_enabled_with(no_color: Option<OsString>, is_terminal: bool) -> bool {
+    let no_color = no_color.is_some_and(|value| !value.is_empty());
+    is_terminal && !no_color
+}
+
+/// Converts the given colour to a table colour.
+pub fn table_color(color: Color) -> comfy_table::Color {
+    match color {
+        Color::Reset => comfy_table::Color::Reset,
+        Color::Black => comfy_table::Color::Black,
+        Color::DarkGrey => comfy_table::Color::DarkGrey,
+        Color::Red => comfy_table::Color::Red,
+        Color::DarkRed => comfy_table::Color::DarkRed,
+        Color::Green => comfy_table::Color::Green,
+        Color::DarkGreen => comfy_table::Color::DarkGreen,
+        Color::Yellow => comfy_table::Color::Yellow,
+        Color::DarkYellow => comfy_table::Color::DarkYellow,
+        Color::Blue => comfy_table::Color::Blue,
+        Color::DarkBlue => comfy_table::Color::DarkBlue,
+        Color::Magenta => comfy_table::Color::Magenta,
+        Color::DarkMagenta => comfy_table::Color::DarkMagenta,
+        Color::Cyan => comfy_table::Color::Cyan,
+        Color::DarkCyan => comfy_table::Color::DarkCyan,
+        Color::White => comfy_table::Color::White,
+        Color::Grey => comfy_table::Color::Grey,
+        Color::Rgb { r, g, b } => comfy_table::Color::Rgb { r, g, b },
+        Color::AnsiValue(n) => comfy_table::Color::AnsiValue(n),
+    }
+}
+
+/// Prompt rendered like the default one, with the colours of a theme.
+pub struct ThemedPrompt {
+    prompt: DefaultPrompt,
+    color: Option<Color>,
+    indicator_color: Option<Color>,
+    right_color: Option<Color>,
+}
+
+impl Prompt for ThemedPrompt {
+    fn render_prompt_left(&self) -> Cow<str> {
+        self.prompt.render_prompt_left()
+    }
+
+    fn render_prompt_right(&self) -> Cow<str> {
+        self.prompt.render_prompt_right()
+    }
+
+    fn render_prompt_indicator(&self, mode: PromptEditMode) -> Cow<str> {
+        self.prompt.render_prompt_indicator(mode)
+    }
+
+    fn render_prompt_multiline_indicator(&self) -> Cow<str> {
+        self.prompt.render_prompt_multiline_indicator()
+    }
+
+    fn render_prompt_history_search_indicator(&self, search: PromptHistorySearch) -> Cow<str> {
+        self.prompt.render_prompt_history_search_indicator(search)
+    }
+
+    fn get_prompt_color(&self) -> Color {
+        self.color.unwrap_or(Color::Reset)
+    }
+
+    fn get_indicator_color(&self) -> Color {
+        self.indicator_color.unwrap_or(Color::Reset)
+    }
+
+    fn get_prompt_right_color(&self) -> Color {
+        self.right_color.unwrap_or(Color::Reset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use crossterm::style::Color;
+
+    use super::{styling_enabled_with, Theme, ThemeKind};
+
+    #[test]
+    fn no_color() {
+        assert!(styling_enabled_with(None, true));
+        assert!(styling_enabled_with(Some(OsString::new()), true));
+        assert!(!styling_enabled_with(Some(OsString::from("1")), true));
+        assert!(!styling_enabled_with(None, false));
+    }
+
+    #[test]
+    fn paint() {
+        let theme = Theme::new(ThemeKind::Dark).with_styling(true);
+        assert_eq!(theme.paint("INBOX", None), "INBOX");
+        assert_eq!(
+            theme.paint("INBOX", Some(Color::Blue)),
+            "\u{1b}[38;5;12mINBOX\u{1b}[39m"
+        );
+
+        let theme = theme.with_styling(false);
+        assert_eq!(theme.paint("INBOX", Some(Color::Blue)), "INBOX");
+    }
+
+    #[test]
+    fn monochrome() {
+        let theme = Theme::new(ThemeKind::Monochrome).with_styling(true);
+        assert_eq!(theme.paint("INBOX", theme.folder_name), "INBOX");
+        assert_eq!(theme.envelope_id, None);
+    }
+}
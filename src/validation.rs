@@ -0,0 +1,289 @@
+//! Template validation module.
+//!
+//! This module contains checks run against templates right before
+//! sending them, in order to catch common mistakes like a forgotten
+//! attachment or a missing subject.
+
+use std::{fmt, fs, path::PathBuf};
+
+use shellexpand_utils::shellexpand_path;
+
+/// Default maximum size of attachments, in bytes (25 MiB).
+pub const DEFAULT_MAX_ATTACHMENT_SIZE: u64 = 25 * 1024 * 1024;
+
+/// Words hinting that the message should have an attachment.
+static ATTACHMENT_KEYWORDS: [&str; 2] = ["attached", "attachment"];
+
+/// MML tags understood by the MML compiler.
+static KNOWN_MML_TAGS: [&str; 4] = ["part", "/part", "multipart", "/multipart"];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Warning {
+    EmptySubject,
+    MissingRecipients,
+    MissingAttachment(String),
+    UnknownMmlTag(String),
+    AttachmentNotFound(PathBuf),
+    OversizedAttachment { path: PathBuf, size: u64, max: u64 },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptySubject => write!(f, "The subject is empty"),
+            Self::MissingRecipients => write!(f, "There is no recipient"),
+            Self::MissingAttachment(word) => {
+                write!(
+                    f,
+                    "The body mentions \"{word}\" but there is no <#part> tag"
+                )
+            }
+            Self::UnknownMmlTag(tag) => write!(f, "Unknown MML tag <#{tag}>"),
+            Self::AttachmentNotFound(path) => write!(f, "Attachment {path:?} not found"),
+            Self::OversizedAttachment { path, size, max } => write!(
+                f,
+                "Attachment {path:?} is too big ({} KiB, max {} KiB)",
+                size / 1024,
+                max / 1024
+            ),
+        }
+    }
+}
+
+/// Splits the given template into its headers and its body.
+fn split_tpl(tpl: &str) -> (Vec<(String, String)>, &str) {
+    let (headers, body) = match tpl.find("\n\n") {
+        Some(pos) => (&tpl[..pos], &tpl[pos + 2..]),
+        None => (tpl, ""),
+    };
+
+    let mut parsed: Vec<(String, String)> = Vec::new();
+
+    for line in headers.lines() {
+        // folded header lines continue the previous one
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, val)) = parsed.last_mut() {
+                val.push(' ');
+                val.push_str(line.trim());
+            }
+            continue;
+        }
+
+        if let Some((key, val)) = line.split_once(':') {
+            parsed.push((key.trim().to_owned(), val.trim().to_owned()));
+        }
+    }
+
+    (parsed, body)
+}
+
+/// Extracts the name of all MML tags of the given body, excluding
+/// escaped ones.
+fn mml_tags(body: &str) -> impl Iterator<Item = (&str, &str)> {
+    body.match_indices("<#").filter_map(move |(pos, _)| {
+        let tag = &body[pos + 2..];
+        let tag = &tag[..tag.find('>')?];
+
+        if tag.starts_with('!') {
+            return None;
+        }
+
+        let name = tag.split_whitespace().next().unwrap_or(tag);
+        Some((name, tag))
+    })
+}
+
+/// Extracts the value of the `filename` property of the given MML tag.
+fn mml_filename(tag: &str) -> Option<String> {
+    let (_, val) = tag.split_once("filename=")?;
+
+    let val = match val.strip_prefix('"') {
+        Some(val) => &val[..val.find('"')?],
+        None => val.split_whitespace().next()?,
+    };
+
+    Some(val.to_owned())
+}
+
+/// Checks the given template, and returns the list of warnings the
+/// user should be aware of before sending it.
+pub fn validate_tpl(tpl: &str, max_attachment_size: u64) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let (headers, body) = split_tpl(tpl);
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, val)| val.as_str())
+            .find(|val| !val.is_empty())
+    };
+
+    if header("Subject").is_none() {
+        warnings.push(Warning::EmptySubject);
+    }
+
+    if ["To", "Cc", "Bcc"].into_iter().all(|h| header(h).is_none()) {
+        warnings.push(Warning::MissingRecipients);
+    }
+
+    let mut has_part = false;
+
+    for (name, tag) in mml_tags(body) {
+        if !KNOWN_MML_TAGS.contains(&name) {
+            warnings.push(Warning::UnknownMmlTag(name.to_owned()));
+            continue;
+        }
+
+        if name != "part" {
+            continue;
+        }
+
+        has_part = true;
+
+        let Some(filename) = mml_filename(tag) else {
+            continue;
+        };
+
+        let path = shellexpand_path(&filename);
+
+        match fs::metadata(&path) {
+            Err(_) => warnings.push(Warning::AttachmentNotFound(path)),
+            Ok(metadata) if metadata.len() > max_attachment_size => {
+                warnings.push(Warning::OversizedAttachment {
+                    path,
+                    size: metadata.len(),
+                    max: max_attachment_size,
+                })
+            }
+            Ok(_) => (),
+        }
+    }
+
+    if !has_part {
+        // quoted lines are ignored, they belong to the replied message
+        let keyword = body
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('>'))
+            .map(str::to_lowercase)
+            .find_map(|line| {
+                ATTACHMENT_KEYWORDS
+                    .into_iter()
+                    .find(|keyword| line.contains(keyword))
+            });
+
+        if let Some(keyword) = keyword {
+            warnings.push(Warning::MissingAttachment(keyword.to_owned()));
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{validate_tpl, Warning, DEFAULT_MAX_ATTACHMENT_SIZE};
+
+    fn validate(tpl: &str) -> Vec<Warning> {
+        validate_tpl(tpl, DEFAULT_MAX_ATTACHMENT_SIZE)
+    }
+
+    #[test]
+    fn valid() {
+        let tpl = "To: bob@localhost\nSubject: Hello\n\nHello Bob!\n";
+        assert_eq!(validate(tpl), vec![]);
+    }
+
+    #[test]
+    fn empty_subject() {
+        let tpl = "To: bob@localhost\nSubject: \n\nHello Bob!\n";
+        assert_eq!(validate(tpl), vec![Warning::EmptySubject]);
+    }
+
+    #[test]
+    fn missing_recipients() {
+        let tpl = "To: \nSubject: Hello\n\nHello Bob!\n";
+        assert_eq!(validate(tpl), vec![Warning::MissingRecipients]);
+
+        // any of To, Cc or Bcc is enough
+        let tpl = "Bcc: bob@localhost\nSubject: Hello\n\nHello Bob!\n";
+        assert_eq!(validate(tpl), vec![]);
+    }
+
+    #[test]
+    fn missing_attachment() {
+        let tpl = "To: bob@localhost\nSubject: Report\n\nSee the report attached.\n";
+        assert_eq!(
+            validate(tpl),
+            vec![Warning::MissingAttachment(String::from("attached"))]
+        );
+
+        // quoted lines belong to the replied message
+        let tpl = "To: bob@localhost\nSubject: Re: Report\n\n> See the attachment.\nThanks!\n";
+        assert_eq!(validate(tpl), vec![]);
+    }
+
+    #[test]
+    fn attachment_with_part() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pdf");
+        fs::write(&path, "report").unwrap();
+
+        let tpl = format!(
+            "To: bob@localhost\nSubject: Report\n\nSee the attachment.\n<#part filename={}><#/part>\n",
+            path.display()
+        );
+
+        assert_eq!(validate(&tpl), vec![]);
+    }
+
+    #[test]
+    fn unknown_mml_tag() {
+        let tpl = "To: bob@localhost\nSubject: Hello\n\n<#prat>Hello<#!part> Bob!\n";
+        assert_eq!(
+            validate(tpl),
+            vec![Warning::UnknownMmlTag(String::from("prat"))]
+        );
+    }
+
+    #[test]
+    fn attachment_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.pdf");
+
+        let tpl = format!(
+            "To: bob@localhost\nSubject: Report\n\n<#part filename=\"{}\"><#/part>\n",
+            path.display()
+        );
+
+        assert!(matches!(
+            validate(&tpl).as_slice(),
+            [Warning::AttachmentNotFound(path)] if path.ends_with("missing.pdf")
+        ));
+    }
+
+    #[test]
+    fn oversized_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pdf");
+        fs::write(&path, [0; 2048]).unwrap();
+
+        let tpl = format!(
+            "To: bob@localhost\nSubject: Report\n\n<#part filename={}><#/part>\n",
+            path.display()
+        );
+
+        let warnings = validate_tpl(&tpl, 1024);
+
+        assert!(matches!(
+            warnings.as_slice(),
+            [Warning::OversizedAttachment {
+                size: 2048,
+                max: 1024,
+                ..
+            }]
+        ));
+    }
+}
@@ -0,0 +1,413 @@
+//! Integration tests driving REPL sessions with scripted inputs,
+//! against a temporary Maildir and a sendmail stub.
+
+// drafts, contacts and id mappers are stored in the XDG data
+// directory, which `dirs` only reads from the environment on Linux
+#![cfg(target_os = "linux")]
+
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    sync::{Arc, Mutex, Once},
+};
+
+use himalaya_repl::{
+    config::TomlConfig,
+    input::ScriptedInput,
+    session::{Session, SessionOptions},
+};
+use tempfile::TempDir;
+
+static XDG_DIRS: Once = Once::new();
+
+/// Points XDG directories to a temporary directory, so that drafts,
+/// contacts and id mappers do not leak into the user ones.
+fn init_xdg_dirs() {
+    XDG_DIRS.call_once(|| {
+        let dir = tempfile::tempdir().unwrap().into_path();
+        std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+        std::env::set_var("XDG_CONFIG_HOME", dir.join("config"));
+    });
+}
+
+/// Writer shared between a session and a test.
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    /// Returns what has been written since the last call.
+    fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.0.lock().unwrap());
+        String::from_utf8(bytes).unwrap()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Env {
+    account: String,
+    dir: TempDir,
+    out: Output,
+    err: Output,
+}
+
+impl Env {
+    /// Creates a Maildir with the INBOX, Archive, Drafts, Sent and
+    /// Trash folders, a sendmail stub writing sent messages to
+    /// `sent.eml`, and an editor writing a message to Bob.
+    ///
+    /// The account name needs to be unique across tests, since XDG
+    /// directories are shared.
+    fn new(account: &str) -> Self {
+        init_xdg_dirs();
+
+        let env = Self {
+            account: account.to_owned(),
+            dir: tempfile::tempdir().unwrap(),
+            out: Output::default(),
+            err: Output::default(),
+        };
+
+        for folder in ["", "Archive", "Drafts", "Sent", "Trash"] {
+            for subdir in ["cur", "new", "tmp"] {
+                fs::create_dir_all(env.folder(folder).join(subdir)).unwrap();
+            }
+        }
+
+        let sent = env.dir.path().join("sent.eml");
+        env.script("sendmail", &format!("cat > {sent:?}"));
+
+        env.script(
+            "editor",
+            "cat > \"$1\" <<'EOF'\n\
+             From: alice@localhost\n\
+             To: bob@localhost\n\
+             Subject: Hello Bob\n\
+             \n\
+             How are you doing?\n\
+             EOF",
+        );
+
+        env
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn folder(&self, folder: &str) -> PathBuf {
+        self.path("mail").join(folder)
+    }
+
+    fn script(&self, name: &str, content: &str) {
+        let path = self.path(name);
+        fs::write(&path, format!("#!/bin/sh\n{content}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn add_message(&self, folder: &str, id: &str, subject: &str, body: &str) {
+        let message = format!(
+            "Message-ID: <{id}@localhost>\r\n\
+             From: Bob <bob@localhost>\r\n\
+             To: alice@localhost\r\n\
+             Subject: {subject}\r\n\
+             Date: Mon, 1 Jan 2024 10:00:00 +0000\r\n\
+             \r\n\
+             {body}\r\n"
+        );
+
+        let path = self.folder(folder).join("cur").join(format!("{id}:2,"));
+        fs::write(path, message).unwrap();
+    }
+
+    /// Lists the file names of the messages of the given folder.
+    fn messages(&self, folder: &str) -> Vec<String> {
+        let mut names: Vec<String> = ["cur", "new"]
+            .into_iter()
+            .flat_map(|subdir| fs::read_dir(self.folder(folder).join(subdir)).unwrap())
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn config(&self) -> TomlConfig {
+        let config = format!(
+            "[accounts.{account}]\n\
+             default = true\n\
+             email = \"alice@localhost\"\n\
+             backend = \"maildir\"\n\
+             maildir.root-dir = {root:?}\n\
+             message.send.backend = \"sendmail\"\n\
+             sendmail.cmd = {sendmail:?}\n\
+             \n\
+             [repl]\n\
+             editor = {editor:?}\n",
+            account = self.account,
+            root = self.folder(""),
+            sendmail = self.path("sendmail"),
+            editor = self.path("editor"),
+        );
+
+        toml::from_str(&config).unwrap()
+    }
+
+    async fn session(&self, script: &[&str]) -> Session {
+        let session = Session::new(
+            self.config(),
+            SessionOptions::default(),
+            Box::new(ScriptedInput::new(script)),
+            Box::new(self.out.clone()),
+            Box::new(self.err.clone()),
+        )
+        .await
+        .unwrap();
+
+        self.out.take();
+        session
+    }
+}
+
+/// Finds the identifier of the envelope matching the given subject
+/// in the output of the `list` command.
+fn find_id(output: &str, subject: &str) -> String {
+    let row = output
+        .lines()
+        .find(|line| line.contains(subject))
+        .unwrap_or_else(|| panic!("cannot find {subject:?} in {output}"));
+
+    row.split('|')
+        .nth(1)
+        .unwrap()
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect()
+}
+
+#[tokio::test]
+async fn select_and_list() {
+    let env = Env::new("select-and-list");
+    env.add_message("", "1", "First message", "Hello");
+    env.add_message("", "2", "Second message", "World");
+    env.add_message("Archive", "3", "Archived message", "Old");
+
+    let mut session = env.session(&[]).await;
+
+    session.execute("list").await.unwrap();
+    assert_eq!(env.err.take(), "Please select a folder first\n");
+
+    session.execute("select INBOX").await.unwrap();
+    assert_eq!(session.folder(), Some("INBOX"));
+
+    session.execute("list").await.unwrap();
+    let output = env.out.take();
+    assert!(output.contains("First message"));
+    assert!(output.contains("Second message"));
+    assert!(!output.contains("Archived message"));
+}
+
+#[tokio::test]
+async fn read_marks_seen() {
+    let env = Env::new("read-marks-seen");
+    env.add_message("", "1", "First message", "Hello from the inbox");
+
+    let mut session = env.session(&[]).await;
+
+    session.execute("select INBOX").await.unwrap();
+    session.execute("list").await.unwrap();
+    let id = find_id(&env.out.take(), "First message");
+
+    session.execute(&format!("peek {id}")).await.unwrap();
+    assert!(env.out.take().contains("Hello from the inbox"));
+    assert_eq!(env.messages(""), ["1:2,"]);
+
+    session.execute(&format!("read {id}")).await.unwrap();
+    assert!(env.out.take().contains("Hello from the inbox"));
+    assert_eq!(env.messages(""), ["1:2,S"]);
+}
+
+#[tokio::test]
+async fn move_and_delete() {
+    let env = Env::new("move-and-delete");
+    env.add_message("", "1", "First message", "Hello");
+    env.add_message("", "2", "Second message", "World");
+
+    let mut session = env.session(&[]).await;
+
+    session.execute("select INBOX").await.unwrap();
+    session.execute("list").await.unwrap();
+    let output = env.out.take();
+    let first = find_id(&output, "First message");
+    let second = find_id(&output, "Second message");
+
+    session
+        .execute(&format!("move {first} Archive"))
+        .await
+        .unwrap();
+    assert_eq!(env.messages("Archive").len(), 1);
+
+    session.execute(&format!("delete {second}")).await.unwrap();
+    assert_eq!(env.messages("Trash").len(), 1);
+
+    assert!(env.messages("").is_empty());
+
+    session.execute("delete abc").await.unwrap();
+    assert_eq!(env.err.take(), "abc: invalid envelope identifier\n");
+}
+
+#[tokio::test]
+async fn write_with_editor() {
+    let env = Env::new("write-with-editor");
+
+    // answers the post-edit prompt
+    let mut session = env.session(&["Send it"]).await;
+
+    session.execute("write").await.unwrap();
+
+    let sent = fs::read_to_string(env.path("sent.eml")).unwrap();
+    assert!(sent.contains("To: bob@localhost"));
+    assert!(sent.contains("Subject: Hello Bob"));
+    assert!(sent.contains("How are you doing?"));
+
+    // sent messages are saved to the Sent folder
+    assert_eq!(env.messages("Sent").len(), 1);
+}
+
+#[tokio::test]
+async fn run_script() {
+    let env = Env::new("run-script");
+    env.add_message("", "1", "First message", "Hello");
+
+    let mut session = env
+        .session(&["help", "select INBOX", "list", "unknown"])
+        .await;
+
+    session.run().await.unwrap();
+
+    let output = env.out.take();
+    assert!(output.starts_with("Available commands: help, folders, select"));
+    assert!(output.contains("First message"));
+    assert!(output.ends_with("Bye!\n"));
+
+    assert_eq!(env.err.take(), "unknown: command not found\n");
+}
+
+#[tokio::test]
+async fn run_after_error() {
+    let env = Env::new("run-after-error");
+
+    let mut session = env.session(&["select Unknown", "list", "help"]).await;
+    session.run().await.unwrap();
+
+    let output = env.out.take();
+    assert!(output.contains("Available commands: help, folders, select"));
+    assert!(output.ends_with("Bye!\n"));
+
+    assert!(!env.err.take().is_empty());
+}
+
+#[tokio::test]
+async fn unknown_draft() {
+    let env = Env::new("unknown-draft");
+    let mut session = env.session(&[]).await;
+
+    session.execute("drafts resume unknown").await.unwrap();
+    assert_eq!(env.err.take(), "unknown: draft not found\n");
+
+    session.execute("drafts delete unknown").await.unwrap();
+    assert_eq!(env.err.take(), "unknown: draft not found\n");
+}
+
+#[tokio::test]
+async fn unknown_outbox_message() {
+    let env = Env::new("unknown-outbox-message");
+    let mut session = env.session(&[]).await;
+
+    session.execute("outbox retry unknown").await.unwrap();
+    assert_eq!(env.err.take(), "unknown: outbox message not found\n");
+
+    session.execute("outbox cancel unknown").await.unwrap();
+    assert_eq!(env.err.take(), "unknown: outbox message not found\n");
+}
+
+#[tokio::test]
+async fn bounce() {
+    let env = Env::new("bounce");
+    env.add_message("", "1", "First message", "Hello");
+
+    let mut session = env.session(&[]).await;
+
+    session.execute("select INBOX").await.unwrap();
+    session.execute("list").await.unwrap();
+    let id = find_id(&env.out.take(), "First message");
+
+    session
+        .execute(&format!("bounce {id} carol"))
+        .await
+        .unwrap();
+    assert_eq!(env.err.take(), "carol: contact not found\n");
+
+    session
+        .execute(&format!("bounce {id} carol@localhost"))
+        .await
+        .unwrap();
+
+    // the original message is sent untouched, after resent headers
+    let original = fs::read(env.folder("").join("cur").join("1:2,")).unwrap();
+    let sent = fs::read(env.path("sent.eml")).unwrap();
+    assert!(sent.starts_with(b"Resent-From: alice@localhost\r\n"));
+    assert!(sent.ends_with(&original));
+}
+
+#[tokio::test]
+async fn template_without_name() {
+    let env = Env::new("template-without-name");
+    let mut session = env.session(&[]).await;
+
+    session.execute("write --template").await.unwrap();
+    assert_eq!(
+        env.err.take(),
+        "The --template option requires a template name\n"
+    );
+}
+
+#[tokio::test]
+async fn unknown_template() {
+    let env = Env::new("unknown-template");
+    env.add_message("", "1", "First message", "Hello");
+
+    let mut session = env.session(&[]).await;
+
+    session.execute("write --template unknown").await.unwrap();
+    assert_eq!(env.err.take(), "unknown: template not found\n");
+
+    session.execute("select INBOX").await.unwrap();
+    session.execute("list").await.unwrap();
+    let id = find_id(&env.out.take(), "First message");
+
+    session
+        .execute(&format!("reply {id} --template unknown"))
+        .await
+        .unwrap();
+    assert_eq!(env.err.take(), "unknown: template not found\n");
+}
+
+#[tokio::test]
+async fn unknown_identity() {
+    let env = Env::new("unknown-identity");
+    let mut session = env.session(&[]).await;
+
+    session.execute("identity unknown").await.unwrap();
+    assert_eq!(env.err.take(), "unknown: identity not found\n");
+}